@@ -1,57 +1,227 @@
 extern crate clap;
+extern crate time;
 
-use std::fs::OpenOptions;
-use std::io::Write;
+use std::io::{ self, BufRead, Write };
 use std::path::Path;
+use std::str::FromStr;
+use atty::{ self, Stream };
 use clap::ArgMatches;
 
 use base::{ Entry, Validation, Error };
+use amount::{ normalize_amount, canonical, readings };
+use crypt;
+use date::{ self, normalize_date, format_date, invalid_date_message };
+use journal;
+use show::{ Delta, read_entries };
+use storage::{ self, Contents, Line };
+use txn::read_transactions;
 
 pub fn run_add(data_path: &Path, matches: &ArgMatches) -> Result<bool, Error> {
     matches.subcommand_matches("add")
            .ok_or(Error::InputError)
-           .and_then(|submatches| build_entry(submatches))
+           .and_then(|submatches| {
+               let entry = if submatches.is_present("delta") {
+                   let entries = read_entries(data_path)?;
+                   build_relative_entry(submatches, &entries, &date::today())
+               } else if submatches.is_present("date") {
+                   build_entry(submatches, &date::today())
+               } else if atty::is(Stream::Stdin) {
+                   let entries = read_entries(data_path)?;
+                   let today = date::today();
+                   let stdin = io::stdin();
+                   let stdout = io::stdout();
+                   prompt_entry(&entries, &today, &mut stdin.lock(), &mut stdout.lock())
+               } else {
+                   println!("Date and amount are required when not running in a terminal");
+                   Err(Error::InputError)
+               };
+               entry.map(|entry| annotate(entry, submatches))
+           })
            .and_then(|entry| {
             match entry.validate() {
-                Validation::Valid => write_to_file(&entry, data_path),
-                Validation::DateParseError => {
-                    println!("Invalid Date {}; must format as yyyy-mm-dd", entry.date_string);
-                    Err(Error::InputError)
-                },
-                Validation::AmountParseError => {
-                    println!("Invalid Amount {}; must be a float", entry.amount_string);
+                Validation::Valid => journal::appended(data_path, "add", &[Line::from(entry)]),
+                invalid => {
+                    println!("{}", invalid_message(&entry, &invalid));
                     Err(Error::InputError)
                 }
             }
         })
 }
 
-fn build_entry(submatches: &ArgMatches) -> Result<Entry, Error> {
+pub fn build_entry(submatches: &ArgMatches, today: &time::Tm) -> Result<Entry, Error> {
     submatches.value_of("date")
-              .and_then(|date| {
+              .and_then(|input| {
                   submatches.value_of("amount")
-                            .map(|amount| Entry::new(date, amount))
+                            .map(|amount| (input, amount))
+              })
+              .ok_or_else(|| {
+                  println!("Both a date and an amount are required, as in add 2016-09-01 1000.00");
+                  Error::InputError
+              })
+              .and_then(|(input, amount)| {
+                  let date = normalize_date(input, today).map_err(|e| {
+                      println!("{}", invalid_date_message(input, &e));
+                      Error::InputError
+                  })?;
+                  normalized_amount(amount).map(|amount| Entry::new(date, amount))
+                                           .map_err(|message| {
+                                               println!("{}", message);
+                                               Error::InputError
+                                           })
               })
-              .ok_or(Error::InputError)
 }
 
-fn write_to_file(entry: &Entry, file_path: &Path) -> Result<bool, Error> {
-    OpenOptions::new()
-                .append(true)
-                .create(true)
-                .open(file_path)
-                .and_then(|mut f| f.write_all(format!("{}", entry).as_bytes()))
-                .map(|_| true)
-                .map_err(|_| Error::WriteError)
+fn build_relative_entry(submatches: &ArgMatches, entries: &[Entry], today: &time::Tm) -> Result<Entry, Error> {
+    let input = submatches.value_of("date").unwrap_or("today");
+    normalize_date(input, today)
+        .map_err(|e| {
+            println!("{}", invalid_date_message(input, &e));
+            Error::InputError
+        })
+        .and_then(|date| {
+            relative_entry(entries, date, submatches.value_of("delta").unwrap_or(""))
+        })
+        .map(|(previous, entry)| {
+            println!("{}", Delta::new(previous, &entry));
+            entry
+        })
+}
+
+fn relative_entry<'a>(entries: &'a [Entry], date: String, delta: &str) -> Result<(&'a Entry, Entry), Error> {
+    let change = normalize_amount(delta).ok_or_else(|| {
+        println!("Invalid Delta {}; must be a number like 250, -1,200 or 2000-1200", delta);
+        Error::InputError
+    })?;
+    let on_or_before = Entry::new(date.as_str(), "0").date();
+    let previous = entries.iter()
+                          .filter(|e| e.date() <= on_or_before)
+                          .max_by_key(|e| &e.date_string)
+                          .ok_or_else(|| {
+                              println!("No entry on or before {} to apply the delta to", date);
+                              Error::InputError
+                          })?;
+    let balance = canonical(previous.amount() + f64::from_str(&change).unwrap_or(0.0));
+    Ok((previous, Entry::new(date, balance)))
+}
+
+fn annotate(entry: Entry, submatches: &ArgMatches) -> Entry {
+    let entry = match submatches.value_of("note") {
+        Some(note) => entry.with_note(note),
+        None => entry
+    };
+    match submatches.values_of("tag") {
+        Some(tags) => entry.with_tags(&tags.collect::<Vec<&str>>()),
+        None => entry
+    }
+}
+
+// Falls back to the raw input so that validation reports what was typed.
+pub fn normalized_amount(input: &str) -> Result<String, String> {
+    normalize_amount(input).ok_or_else(|| match readings(input) {
+        Some((decimal, grouped)) => format!("Ambiguous Amount {}; did you mean {} or {}?", input, decimal, grouped),
+        None => invalid_amount_message(input)
+    })
+}
+
+fn invalid_amount_message(amount: &str) -> String {
+    format!("Invalid Amount {}; must be a number like 1234.56, $1,234.56 or 1200+35.50", amount)
+}
+
+pub fn invalid_message(entry: &Entry, validation: &Validation) -> String {
+    match *validation {
+        Validation::DateParseError =>
+            format!("Invalid Date {}; must format as yyyy-mm-dd", entry.date_string),
+        Validation::AmountParseError => invalid_amount_message(&entry.amount_string),
+        Validation::TagParseError =>
+            format!("Invalid Tag in {}; tags are single words without commas", entry.tags.join(", ")),
+        Validation::Valid => String::new()
+    }
+}
+
+fn prompt_entry<R, W>(entries: &[Entry], today: &time::Tm, input: &mut R, output: &mut W) -> Result<Entry, Error>
+    where R: BufRead, W: Write {
+    let default = format_date(today);
+    let date = prompt_until_valid(input, output, &format!("Date [{}]: ", default), &default, |date| {
+        normalize_date(date, today).map_err(|e| invalid_date_message(date, &e))
+    })?;
+
+    let new_date = Entry::new(date.as_str(), "0").date();
+    let previous = entries.iter()
+                          .filter(|e| e.date() <= new_date)
+                          .max_by_key(|e| &e.date_string);
+    match previous {
+        Some(entry) => writeln!(output, "Previous balance: {} on {}", entry.amount_string,
+                                                                      entry.date_string),
+        None => writeln!(output, "No previous balance")
+    }.map_err(|_| Error::WriteError)?;
+
+    let amount = prompt_until_valid(input, output, "Amount: ", "", normalized_amount)?;
+
+    let entry = Entry::new(date, amount);
+    if let Some(previous) = previous {
+        writeln!(output, "{}", Delta::new(previous, &entry)).map_err(|_| Error::WriteError)?;
+    }
+    Ok(entry)
+}
+
+fn prompt_until_valid<R, W, F>(input: &mut R,
+                               output: &mut W,
+                               prompt: &str,
+                               default: &str,
+                               check: F) -> Result<String, Error>
+    where R: BufRead, W: Write, F: Fn(&str) -> Result<String, String> {
+    loop {
+        write!(output, "{}", prompt).and_then(|_| output.flush())
+                                    .map_err(|_| Error::WriteError)?;
+        let mut line = String::new();
+        match input.read_line(&mut line) {
+            Ok(0) | Err(_) => return Err(Error::InputError),
+            Ok(_) => {}
+        }
+        let value = match line.trim() {
+            "" => default,
+            value => value
+        };
+        match check(value) {
+            Ok(normalized) => return Ok(normalized),
+            Err(message) => writeln!(output, "{}", message).map_err(|_| Error::WriteError)?
+        }
+    }
+}
+
+/// Appends one ledger line: a balance `Entry` or a transaction.
+pub fn write_to_file<T>(record: &T, file_path: &Path) -> Result<bool, Error>
+    where T: Clone + Into<Line> {
+    storage::open(file_path).append(&record.clone().into())
+                            .map(|_| true)
+                            .map_err(crypt::write_error)
+}
+
+/// Replaces the balance entries in the ledger, for changes that cannot be
+/// appended; transactions already in the file are kept.
+pub fn write_entries(entries: &[Entry], file_path: &Path) -> Result<bool, Error> {
+    let transactions = read_transactions(file_path).map_err(|e| {
+        println!("Could not read the transactions in {}: {}; the ledger was not rewritten", file_path.display(), e);
+        crypt::read_error(e)
+    })?;
+    storage::open(file_path).save(&Contents { entries: entries.to_vec(), transactions })
+                            .map(|_| true)
+                            .map_err(crypt::write_error)
 }
 
 #[cfg(test)]
 mod test {
-    use std::fs::OpenOptions;
+    use std::env;
+    use std::fs::{ self, OpenOptions };
     use std::io::{ Write, BufRead, BufReader };
     use std::path::Path;
     use base::Entry;
-    use super::{ write_to_file };
+    use super::time;
+    use super::{ write_entries, write_to_file, prompt_entry, relative_entry };
+
+    fn today(date: &str) -> time::Tm {
+        time::strptime(date, "%Y-%m-%d").unwrap()
+    }
 
     #[test]
     fn write_entry_to_file() {
@@ -77,7 +247,7 @@ mod test {
                        existing_lines);
         }
 
-        let res = write_to_file(&valid_entry, &test_file);
+        let res = write_to_file(&valid_entry, test_file);
         assert!(res.is_ok());
         {
             let f = OpenOptions::new()
@@ -106,4 +276,117 @@ mod test {
         assert!(cleanup.is_ok());
         assert!(sync.is_ok());
     }
+
+    #[test]
+    fn prompts_with_defaults_and_previous_delta() {
+        let entries = vec![Entry::new("2016-01-01", "1000.00"),
+                           Entry::new("2016-02-01", "1500.00"),
+                           Entry::new("2016-04-01", "900.00")
+                          ];
+        let mut input = "\n$1,250.00\n".as_bytes();
+        let mut output = Vec::new();
+
+        let entry = prompt_entry(&entries, &today("2016-03-05"), &mut input, &mut output).unwrap();
+
+        assert_eq!(entry, Entry::new("2016-03-05", "1250.00"));
+        assert_eq!(String::from_utf8(output).unwrap(),
+                   "Date [2016-03-05]: Previous balance: 1500.00 on 2016-02-01\n\
+                    Amount: 2016-02-01 -> 2016-03-05: 1500.00 -> 1250.00 | -250\n");
+    }
+
+    #[test]
+    fn prompts_with_the_latest_prior_balance_of_an_unsorted_ledger() {
+        let entries = vec![Entry::new("2016-02-01", "1500.00"),
+                           Entry::new("2016-04-01", "900.00"),
+                           Entry::new("2016-01-01", "1000.00"),
+                           Entry::new("2016-02-01", "1600.00")
+                          ];
+        let mut input = "\n$1,250.00\n".as_bytes();
+        let mut output = Vec::new();
+
+        prompt_entry(&entries, &today("2016-03-05"), &mut input, &mut output).unwrap();
+
+        assert!(String::from_utf8(output).unwrap().starts_with("Date [2016-03-05]: Previous balance: 1600.00 on 2016-02-01\n"));
+    }
+
+    #[test]
+    fn reprompts_on_invalid_input() {
+        let mut input = "9/1/16\nSep 1 2016\nhello\n1000\n".as_bytes();
+        let mut output = Vec::new();
+
+        let entry = prompt_entry(&[], &today("2016-10-01"), &mut input, &mut output).unwrap();
+
+        assert_eq!(entry, Entry::new("2016-09-01", "1000.00"));
+        let transcript = String::from_utf8(output).unwrap();
+        assert!(transcript.contains("Ambiguous Date 9/1/16; did you mean 2016-09-01?"));
+        assert!(transcript.contains("Invalid Amount hello"));
+        assert!(transcript.contains("No previous balance"));
+    }
+
+    #[test]
+    fn reprompts_on_an_ambiguous_amount() {
+        let mut input = "2016-09-01\n12.345\n12.3450\n".as_bytes();
+        let mut output = Vec::new();
+
+        let entry = prompt_entry(&[], &today("2016-10-01"), &mut input, &mut output).unwrap();
+
+        assert_eq!(entry, Entry::new("2016-09-01", "12.345"));
+        assert!(String::from_utf8(output).unwrap().contains("Ambiguous Amount 12.345; did you mean 12.345 or 12345.00?"));
+    }
+
+    #[test]
+    fn prompt_fails_on_end_of_input() {
+        let mut input = "2016-09-01\n".as_bytes();
+        let mut output = Vec::new();
+
+        assert!(prompt_entry(&[], &today("2016-10-01"), &mut input, &mut output).is_err());
+    }
+
+    #[test]
+    fn applies_delta_to_latest_prior_entry() {
+        let entries = vec![Entry::new("2016-01-01", "1000.00"),
+                           Entry::new("2016-02-01", "1500.00"),
+                           Entry::new("2016-04-01", "900.00")
+                          ];
+
+        let (previous, entry) = relative_entry(&entries, "2016-03-05".to_string(), "+250").unwrap();
+        assert_eq!(previous, &entries[1]);
+        assert_eq!(entry, Entry::new("2016-03-05", "1750.00"));
+
+        let (_, entry) = relative_entry(&entries, "2016-04-01".to_string(), "2000-2100").unwrap();
+        assert_eq!(entry, Entry::new("2016-04-01", "800.00"));
+    }
+
+    #[test]
+    fn applies_delta_to_the_latest_prior_entry_of_an_unsorted_ledger() {
+        let entries = vec![Entry::new("2016-02-01", "1500.00"),
+                           Entry::new("2016-04-01", "900.00"),
+                           Entry::new("2016-01-01", "1000.00"),
+                           Entry::new("2016-02-01", "1600.00")
+                          ];
+
+        let (previous, entry) = relative_entry(&entries, "2016-03-05".to_string(), "+250").unwrap();
+        assert_eq!(previous, &entries[3]);
+        assert_eq!(entry, Entry::new("2016-03-05", "1850.00"));
+    }
+
+    #[test]
+    fn refuses_delta_without_prior_entry() {
+        let entries = vec![Entry::new("2016-02-01", "1500.00")];
+
+        assert!(relative_entry(&entries, "2016-01-15".to_string(), "250").is_err());
+        assert!(relative_entry(&[], "2016-01-15".to_string(), "250").is_err());
+        assert!(relative_entry(&entries, "2016-03-01".to_string(), "lots").is_err());
+    }
+
+    #[test]
+    fn keeps_the_ledger_when_its_transactions_cannot_be_read() {
+        let path = env::temp_dir().join(format!("budget_add_unreadable_{}", std::process::id()));
+        let contents = b"2016-01-01|1000.00\ntxn|2016-01-15|-40.00|payee=Caf\xe9\n".to_vec();
+        fs::write(&path, &contents).unwrap();
+
+        assert!(write_entries(&[Entry::new("2016-02-01", "900.00")], &path).is_err());
+        assert_eq!(fs::read(&path).unwrap(), contents);
+        fs::remove_file(path).unwrap();
+    }
 }
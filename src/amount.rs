@@ -0,0 +1,205 @@
+const CURRENCY_SYMBOLS: [char; 5] = ['$', '€', '£', '¥', '₹'];
+
+/// Normalizes human amount input to the canonical ledger form, e.g. `1234.56`.
+///
+/// Accepts currency symbols, `,`, `.` or space thousands separators with
+/// either `.` or `,` as the decimal mark, accounting negatives like
+/// `(250.00)`, and `+ - * /` expressions such as `1200+35.50`. Exponents and
+/// non-finite values are rejected.
+pub fn normalize_amount(input: &str) -> Option<String> {
+    let cleaned = input.chars()
+                       .filter(|c| !CURRENCY_SYMBOLS.contains(c))
+                       .collect::<String>();
+    let trimmed = cleaned.trim();
+    let value = if trimmed.starts_with('(') && trimmed.ends_with(')') {
+        parse_number(&trimmed[1..trimmed.len() - 1]).map(|v| -v)
+    } else {
+        evaluate(trimmed)
+    }?;
+
+    if !value.is_finite() {
+        return None
+    }
+    Some(canonical(value))
+}
+
+pub fn canonical(value: f64) -> String {
+    let cents = (value * 100.0).round();
+    let formatted = if (value * 100.0 - cents).abs() < 1e-6 {
+        format!("{:.2}", cents / 100.0)
+    } else {
+        format!("{}", value)
+    };
+    if formatted == "-0.00" { "0.00".to_string() } else { formatted }
+}
+
+fn evaluate(expression: &str) -> Option<f64> {
+    let mut terms = Vec::new();
+    let mut operators = Vec::new();
+    let mut operand = String::new();
+    for c in expression.chars() {
+        let is_operator = "+-*/".contains(c);
+        if is_operator && !operand.trim().is_empty() {
+            terms.push(parse_number(&operand)?);
+            operators.push(c);
+            operand.clear();
+        } else {
+            operand.push(c);
+        }
+    }
+    terms.push(parse_number(&operand)?);
+
+    // multiplication and division bind tighter than addition and subtraction
+    let mut sums = vec![terms[0]];
+    let mut signs = Vec::new();
+    for (operator, term) in operators.iter().zip(terms.iter().skip(1)) {
+        match *operator {
+            '*' => { let last = sums.pop()?; sums.push(last * term) },
+            '/' => { let last = sums.pop()?; sums.push(last / term) },
+            sign => { signs.push(sign); sums.push(*term) }
+        }
+    }
+    Some(signs.iter().zip(sums.iter().skip(1))
+                     .fold(sums[0], |total, (sign, term)| {
+                         if *sign == '+' { total + term } else { total - term }
+                     }))
+}
+
+fn parse_number(input: &str) -> Option<f64> {
+    let compact = input.chars().filter(|c| !c.is_whitespace()).collect::<String>();
+    let (negative, digits) = match compact.strip_prefix('-') {
+        Some(rest) => (true, rest),
+        None => (false, compact.strip_prefix('+').unwrap_or(&compact))
+    };
+    if digits.is_empty() || !digits.chars().all(|c| c.is_ascii_digit() || c == ',' || c == '.') {
+        return None
+    }
+
+    if ambiguous(digits) {
+        return None
+    }
+    let decimal_mark = decimal_mark(digits);
+    let (whole, fraction) = match decimal_mark {
+        Some(index) => (&digits[..index], &digits[index + 1..]),
+        None => (digits, "")
+    };
+    if fraction.contains(',') || fraction.contains('.') || !valid_grouping(whole) {
+        return None
+    }
+
+    let plain = format!("{}.{}", whole.replace([',', '.'], ""), fraction);
+    plain.trim_end_matches('.')
+         .parse::<f64>()
+         .ok()
+         .map(|v| if negative { -v } else { v })
+}
+
+// The last separator is the decimal mark when both kinds appear, and a
+// repeated one separates thousands; otherwise a lone one is the decimal
+// mark.
+fn decimal_mark(digits: &str) -> Option<usize> {
+    let last = digits.rfind([',', '.'])?;
+    let mark = digits[last..].chars().next()?;
+    let other = if mark == ',' { '.' } else { ',' };
+    if !digits.contains(other) && digits.matches(mark).count() > 1 {
+        return None
+    }
+    Some(last)
+}
+
+/// The only separator has exactly three digits after it, and the digits
+/// before it could start a grouped number, so `1.234` may be either 1.234
+/// or 1234. Nothing before it, a leading zero or more than three digits
+/// settles it as a decimal mark.
+fn ambiguous(digits: &str) -> bool {
+    match digits.rfind([',', '.']) {
+        Some(last) => {
+            let whole = &digits[..last];
+            digits.matches([',', '.']).count() == 1 && digits.len() - last - 1 == 3 &&
+                (1..=3).contains(&whole.len()) && !whole.starts_with('0')
+        },
+        None => false
+    }
+}
+
+/// Both readings of an amount like `12.345` that `normalize_amount` turns
+/// down as ambiguous: with the separator as a decimal mark, then as a
+/// thousands mark.
+pub fn readings(input: &str) -> Option<(String, String)> {
+    let cleaned = input.chars()
+                       .filter(|c| !CURRENCY_SYMBOLS.contains(c) && !c.is_whitespace())
+                       .collect::<String>();
+    let (sign, digits) = match cleaned.strip_prefix('-') {
+        Some(rest) => (-1.0, rest),
+        None => (1.0, cleaned.as_str())
+    };
+    if !ambiguous(digits) || !digits.chars().all(|c| c.is_ascii_digit() || c == ',' || c == '.') {
+        return None
+    }
+    let decimal = digits.replace(',', ".").parse::<f64>().ok()?;
+    let grouped = digits.replace([',', '.'], "").parse::<f64>().ok()?;
+    Some((canonical(sign * decimal), canonical(sign * grouped)))
+}
+
+fn valid_grouping(whole: &str) -> bool {
+    let groups = whole.split([',', '.']).collect::<Vec<&str>>();
+    groups.len() == 1 ||
+        (!groups[0].is_empty() && groups[0].len() <= 3 && groups[1..].iter().all(|g| g.len() == 3))
+}
+
+#[cfg(test)]
+mod test {
+    use super::{ normalize_amount, readings };
+
+    #[test]
+    fn normalizes_plain_amounts() {
+        assert_eq!(normalize_amount("1000"), Some("1000.00".to_string()));
+        assert_eq!(normalize_amount("1250.5"), Some("1250.50".to_string()));
+        assert_eq!(normalize_amount("-50"), Some("-50.00".to_string()));
+        assert_eq!(normalize_amount("0.125"), Some("0.125".to_string()));
+    }
+
+    #[test]
+    fn normalizes_currency_and_separators() {
+        assert_eq!(normalize_amount("$1,234.56"), Some("1234.56".to_string()));
+        assert_eq!(normalize_amount("1 234,56"), Some("1234.56".to_string()));
+        assert_eq!(normalize_amount("€1.234.567,89"), Some("1234567.89".to_string()));
+        assert_eq!(normalize_amount("1,234,567"), Some("1234567.00".to_string()));
+        assert_eq!(normalize_amount("0,125"), Some("0.125".to_string()));
+        assert_eq!(normalize_amount("1234.567"), Some("1234.567".to_string()));
+        assert_eq!(normalize_amount("12,5"), Some("12.50".to_string()));
+        assert_eq!(normalize_amount("(250.00)"), Some("-250.00".to_string()));
+        assert_eq!(normalize_amount("-$50"), Some("-50.00".to_string()));
+    }
+
+    #[test]
+    fn rejects_a_lone_separator_before_three_digits_with_both_readings() {
+        assert_eq!(normalize_amount("1,234"), None);
+        assert_eq!(normalize_amount("12.345"), None);
+        assert_eq!(normalize_amount("$1.234"), None);
+        assert_eq!(readings("12.345"), Some(("12.345".to_string(), "12345.00".to_string())));
+        assert_eq!(readings("-$1,234"), Some(("-1.234".to_string(), "-1234.00".to_string())));
+        assert_eq!(readings("1,234.56"), None);
+        assert_eq!(readings("0.125"), None);
+    }
+
+    #[test]
+    fn evaluates_simple_expressions() {
+        assert_eq!(normalize_amount("1200+35.50"), Some("1235.50".to_string()));
+        assert_eq!(normalize_amount("1200 - 35.50 - 4"), Some("1160.50".to_string()));
+        assert_eq!(normalize_amount("100+2*25"), Some("150.00".to_string()));
+        assert_eq!(normalize_amount("-10+5"), Some("-5.00".to_string()));
+    }
+
+    #[test]
+    fn rejects_non_finite_and_malformed_amounts() {
+        assert_eq!(normalize_amount("NaN"), None);
+        assert_eq!(normalize_amount("inf"), None);
+        assert_eq!(normalize_amount("1e3"), None);
+        assert_eq!(normalize_amount("1/0"), None);
+        assert_eq!(normalize_amount("1,2,3"), None);
+        assert_eq!(normalize_amount("hello"), None);
+        assert_eq!(normalize_amount("100+"), None);
+        assert_eq!(normalize_amount(""), None);
+    }
+}
@@ -0,0 +1,161 @@
+extern crate clap;
+
+use std::fmt;
+use std::path::Path;
+use std::str::FromStr;
+use clap::ArgMatches;
+
+use amount::canonical;
+use base::{ Entry, Error, date_value };
+use crypt;
+use date::{ self, days_between, format_date };
+use show::read_file;
+
+/// How to estimate a balance between two recorded entries.
+#[derive(PartialEq, Eq, Debug, Clone, Copy)]
+pub enum Interpolation {
+    /// The earlier balance carries forward until the next entry.
+    Step,
+    /// The balance moves on a straight line from one entry to the next.
+    Linear
+}
+
+impl FromStr for Interpolation {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, ()> {
+        match s {
+            "step" => Ok(Interpolation::Step),
+            "linear" => Ok(Interpolation::Linear),
+            _ => Err(())
+        }
+    }
+}
+
+impl fmt::Display for Interpolation {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Interpolation::Step => write!(f, "step"),
+            Interpolation::Linear => write!(f, "linear")
+        }
+    }
+}
+
+/// The ledger's balance on a date: either an entry recorded on that day or
+/// an estimate from the entries around it.
+#[derive(PartialEq, Debug)]
+pub struct Balance {
+    pub amount: f64,
+    /// How the amount was estimated; `None` when it was recorded.
+    pub estimate: Option<Interpolation>
+}
+
+impl fmt::Display for Balance {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.estimate {
+            Some(mode) => write!(f, "{} estimated ({})", canonical(self.amount), mode),
+            None => write!(f, "{} recorded", canonical(self.amount))
+        }
+    }
+}
+
+/// The balance on `date`, estimated by `mode` between entries. After the
+/// last entry the last balance holds in either mode; there is no balance
+/// before the first entry.
+pub fn balance_on(entries: &[Entry], date: &str, mode: Interpolation) -> Option<Balance> {
+    if let Some(entry) = entries.iter().rev().find(|e| e.date_string == date) {
+        return Some(Balance { amount: entry.amount(), estimate: None })
+    }
+    let before = entries.iter()
+                        .filter(|e| e.date_string.as_str() < date)
+                        .max_by_key(|e| &e.date_string)?;
+    let after = entries.iter()
+                       .rev()
+                       .filter(|e| e.date_string.as_str() > date)
+                       .min_by_key(|e| &e.date_string);
+    let amount = match (mode, after) {
+        (Interpolation::Linear, Some(after)) => {
+            let elapsed = days_between(&before.date_string, date)? as f64;
+            let span = days_between(&before.date_string, &after.date_string)? as f64;
+            before.amount() + (after.amount() - before.amount()) * elapsed / span
+        },
+        _ => before.amount()
+    };
+    Some(Balance { amount, estimate: Some(mode) })
+}
+
+pub fn run_balance(data_path: &Path, matches: &ArgMatches) -> Result<bool, Error> {
+    let submatches = matches.subcommand_matches("balance").ok_or(Error::InputError)?;
+    let date = date_value(submatches, "on")?.unwrap_or_else(|| format_date(&date::today()));
+    let mode = submatches.value_of("interpolation")
+                         .map_or(Ok(Interpolation::Linear), Interpolation::from_str)
+                         .map_err(|_| Error::InputError)?;
+    let entries = read_file(data_path).map_err(crypt::read_error)?;
+    match balance_on(&entries, &date, mode) {
+        Some(balance) => {
+            println!("{}: {}", date, balance);
+            Ok(true)
+        },
+        None => {
+            println!("No balance on {}; the ledger starts {}", date,
+                     entries.iter().map(|e| e.date_string.as_str()).min().unwrap_or("with no entries"));
+            Ok(false)
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use base::Entry;
+    use super::{ Balance, Interpolation, balance_on };
+
+    fn entries() -> Vec<Entry> {
+        vec![Entry::new("2016-01-01", "1000.00"),
+             Entry::new("2016-01-11", "2000.00"),
+             Entry::new("2016-02-01", "1500.00")
+            ]
+    }
+
+    fn estimated(amount: f64, mode: Interpolation) -> Option<Balance> {
+        Some(Balance { amount, estimate: Some(mode) })
+    }
+
+    #[test]
+    fn recorded_balance_on_entry_date() {
+        let balance = balance_on(&entries(), "2016-01-11", Interpolation::Step).unwrap();
+
+        assert_eq!(balance.estimate, None);
+        assert_eq!(format!("{}", balance), "2000.00 recorded");
+    }
+
+    #[test]
+    fn interpolates_between_entries() {
+        assert_eq!(balance_on(&entries(), "2016-01-05", Interpolation::Linear),
+                   estimated(1400.0, Interpolation::Linear));
+        assert_eq!(balance_on(&entries(), "2016-01-05", Interpolation::Step),
+                   estimated(1000.0, Interpolation::Step));
+        assert_eq!(format!("{}", balance_on(&entries(), "2016-01-05", Interpolation::Linear).unwrap()),
+                   "1400.00 estimated (linear)");
+    }
+
+    #[test]
+    fn holds_last_balance_and_has_none_before_first() {
+        assert_eq!(balance_on(&entries(), "2016-03-01", Interpolation::Linear),
+                   estimated(1500.0, Interpolation::Linear));
+        assert_eq!(balance_on(&entries(), "2015-12-31", Interpolation::Step), None);
+    }
+
+    #[test]
+    fn estimates_between_the_nearest_entries_of_an_unsorted_ledger() {
+        let entries = vec![Entry::new("2016-02-01", "1500.00"),
+                           Entry::new("2016-01-11", "2000.00"),
+                           Entry::new("2016-01-01", "1000.00"),
+                           Entry::new("2016-01-01", "1200.00")
+                          ];
+
+        assert_eq!(balance_on(&entries, "2016-01-05", Interpolation::Step),
+                   estimated(1200.0, Interpolation::Step));
+        assert_eq!(balance_on(&entries, "2016-01-05", Interpolation::Linear),
+                   estimated(1520.0, Interpolation::Linear));
+    }
+}
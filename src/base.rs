@@ -1,45 +1,151 @@
 extern crate time;
 
+use std::collections::BTreeMap;
+use std::convert::TryFrom;
 use std::fmt;
 use std::path::PathBuf;
 use std::str::FromStr;
 use clap::ArgMatches;
+use serde::ser::{ Serialize, SerializeMap, Serializer };
+
+use date::{ self, normalize_date, invalid_date_message };
 
 #[derive(PartialEq, Eq, Debug)]
 pub enum Error {
     ReadError,
     WriteError,
-    InputError
+    InputError,
+    /// An encrypted ledger that would not open with the passphrase given.
+    PassphraseError
 }
 
 #[derive(PartialEq, Eq, Debug)]
 pub enum Validation {
     Valid,
     DateParseError,
-    AmountParseError
+    AmountParseError,
+    TagParseError
 }
 
-#[derive(PartialEq, Eq, Debug)]
+/// The value of one named field of an entry.
+#[derive(PartialEq, Eq, Debug, Clone, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum FieldValue {
+    Flag(bool),
+    List(Vec<String>),
+    Text(String)
+}
+
+/// Fields holding a list, written `a,b` in a ledger line.
+const LIST_FIELDS: [&str; 1] = ["tags"];
+
+/// A balance on a date. Its `fields` are the one model of what an entry
+/// holds: `Display` and `from_line` give them as the `|`-delimited ledger
+/// line, and `Serialize` and `Deserialize` as a JSON object.
+#[derive(PartialEq, Eq, Debug, Clone, Deserialize)]
+#[serde(try_from = "BTreeMap<String, FieldValue>")]
 pub struct Entry {
     pub date_string: String,
-    pub amount_string: String
+    pub amount_string: String,
+    pub note: Option<String>,
+    pub tags: Vec<String>,
+    /// Set once the balance has been checked against a statement.
+    pub reconciled: bool
 }
 
 impl Entry {
     pub fn new<S>(date_string: S, amount_string: S) -> Self
         where S: Into<String> {
         Entry { date_string: date_string.into(),
-                amount_string: amount_string.into()
+                amount_string: amount_string.into(),
+                note: None,
+                tags: Vec::new(),
+                reconciled: false
               }
     }
 
+    pub fn with_note<S>(mut self, note: S) -> Self
+        where S: Into<String> {
+        self.note = Some(note.into());
+        self
+    }
+
+    pub fn with_tags<S>(mut self, tags: &[S]) -> Self
+        where S: AsRef<str> {
+        self.tags = tags.iter().map(|t| t.as_ref().to_string()).collect();
+        self
+    }
+
+    pub fn with_reconciled(mut self) -> Self {
+        self.reconciled = true;
+        self
+    }
+
+    /// The entry's fields in ledger order, leaving out those not set.
+    pub fn fields(&self) -> Vec<(&'static str, FieldValue)> {
+        let mut fields = vec![("date", FieldValue::Text(self.date_string.clone())),
+                              ("amount", FieldValue::Text(self.amount_string.clone()))];
+        if let Some(ref note) = self.note {
+            fields.push(("note", FieldValue::Text(note.clone())));
+        }
+        if !self.tags.is_empty() {
+            fields.push(("tags", FieldValue::List(self.tags.clone())));
+        }
+        if self.reconciled {
+            fields.push(("reconciled", FieldValue::Flag(true)));
+        }
+        fields
+    }
+
+    /// Sets a field read back from either format. Unknown fields are
+    /// ignored, so that newer files still read.
+    pub fn set_field(&mut self, name: &str, value: FieldValue) -> Result<(), String> {
+        match (name, value) {
+            ("date", FieldValue::Text(date)) => self.date_string = date,
+            ("amount", FieldValue::Text(amount)) => self.amount_string = amount,
+            ("note", FieldValue::Text(note)) => self.note = Some(note),
+            ("tags", FieldValue::List(tags)) => self.tags = tags,
+            ("reconciled", FieldValue::Flag(reconciled)) => self.reconciled = reconciled,
+            ("date", _) | ("amount", _) | ("note", _) | ("tags", _) | ("reconciled", _) =>
+                return Err(format!("Invalid {} field", name)),
+            _ => {}
+        }
+        Ok(())
+    }
+
+    /// Names of the fields that differ from `other`.
+    pub fn differences(&self, other: &Entry) -> Vec<&'static str> {
+        let (mine, theirs) = (self.fields(), other.fields());
+        let mut names = Vec::new();
+        for field in mine.iter().filter(|f| !theirs.contains(f)).chain(theirs.iter().filter(|f| !mine.contains(f))) {
+            if !names.contains(&field.0) {
+                names.push(field.0);
+            }
+        }
+        names
+    }
+
+    /// Reads `date|amount`, optionally followed by `|note=...`, `|tags=a,b`
+    /// and `|reconciled` fields; fields that do not fit are ignored.
     pub fn from_line<S>(line: S) -> Self
         where S: Into<String> {
         let line_str = line.into();
-        let strs = line_str.split("|")
-                           .into_iter()
-                           .collect::<Vec<&str>>();
-        Entry::new(strs[0], strs[1])
+        let strs = split_fields(&line_str);
+        let mut entry = Entry::new(strs[0].as_str(), strs.get(1).map_or("", |a| a.as_str()));
+        for field in strs.iter().skip(2) {
+            let value = match field.split_once('=') {
+                Some((name, list)) if LIST_FIELDS.contains(&name) =>
+                    (name, FieldValue::List(list.split(',').filter(|t| !t.is_empty()).map(|t| t.to_string()).collect())),
+                Some((name, text)) => (name, FieldValue::Text(text.to_string())),
+                None => (field.as_str(), FieldValue::Flag(true))
+            };
+            let _ = entry.set_field(value.0, value.1);
+        }
+        entry
+    }
+
+    pub fn has_tag(&self, tag: &str) -> bool {
+        self.tags.iter().any(|t| t.eq_ignore_ascii_case(tag))
     }
 
     pub fn validate(&self) -> Validation {
@@ -49,6 +155,9 @@ impl Entry {
         if !self.valid_amount() {
             return Validation::AmountParseError
         }
+        if !self.tags.iter().all(|t| valid_tag(t)) {
+            return Validation::TagParseError
+        }
         return Validation::Valid
     }
 
@@ -60,10 +169,8 @@ impl Entry {
     }
 
     fn valid_amount(&self) -> bool {
-        match f64::from_str(&self.amount_string) {
-            Ok(_) => true,
-            Err(_) => false
-        }
+        self.amount_string.chars().all(|c| c.is_ascii_digit() || c == '.' || c == '-') &&
+            f64::from_str(&self.amount_string).map(|v| v.is_finite()).unwrap_or(false)
     }
 
     pub fn date(&self) -> time::Tm {
@@ -77,8 +184,75 @@ impl Entry {
 
 impl fmt::Display for Entry {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        write!(f, "{}|{}\n", self.date_string, self.amount_string)
+        write!(f, "{}|{}", self.date_string, self.amount_string)?;
+        for (name, value) in self.fields().into_iter().skip(2) {
+            match value {
+                FieldValue::Text(text) => write!(f, "|{}={}", name, escape(&text))?,
+                FieldValue::List(list) => write!(f, "|{}={}", name, list.join(","))?,
+                FieldValue::Flag(true) => write!(f, "|{}", name)?,
+                FieldValue::Flag(false) => {}
+            }
+        }
+        writeln!(f)
+    }
+}
+
+impl Serialize for Entry {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let fields = self.fields();
+        let mut map = serializer.serialize_map(Some(fields.len()))?;
+        for (name, value) in &fields {
+            map.serialize_entry(name, value)?;
+        }
+        map.end()
+    }
+}
+
+impl TryFrom<BTreeMap<String, FieldValue>> for Entry {
+    type Error = String;
+
+    fn try_from(fields: BTreeMap<String, FieldValue>) -> Result<Self, String> {
+        if !fields.contains_key("date") || !fields.contains_key("amount") {
+            return Err("an entry needs a date and an amount".to_string())
+        }
+        let mut entry = Entry::new("", "");
+        for (name, value) in fields {
+            entry.set_field(&name, value)?;
+        }
+        Ok(entry)
+    }
+}
+
+/// Tags are single words, so they need no escaping in the ledger line.
+pub fn valid_tag(tag: &str) -> bool {
+    !tag.is_empty() && !tag.chars().any(|c| c.is_whitespace() || c == ',' || c == '|' || c == '\\')
+}
+
+pub fn escape(field: &str) -> String {
+    field.replace('\\', "\\\\")
+         .replace('|', "\\|")
+         .replace('\n', "\\n")
+         .replace('\r', "\\r")
+}
+
+// Splits on `|` not preceded by a backslash, undoing `escape`.
+pub fn split_fields(line: &str) -> Vec<String> {
+    let mut fields = vec![String::new()];
+    let mut chars = line.chars();
+    while let Some(c) = chars.next() {
+        let field = fields.last_mut().unwrap();
+        match c {
+            '\\' => match chars.next() {
+                Some('n') => field.push('\n'),
+                Some('r') => field.push('\r'),
+                Some(other) => field.push(other),
+                None => field.push('\\')
+            },
+            '|' => fields.push(String::new()),
+            c => field.push(c)
+        }
     }
+    fields
 }
 
 pub fn filepath(matches: &ArgMatches, default_path: Option<PathBuf>) -> Option<PathBuf> {
@@ -93,12 +267,48 @@ pub fn filepath(matches: &ArgMatches, default_path: Option<PathBuf>) -> Option<P
     }
 }
 
+/// Reads a date argument in any format `normalize_date` accepts, reporting
+/// unusable input before failing.
+pub fn date_value(matches: &ArgMatches, name: &str) -> Result<Option<String>, Error> {
+    match matches.value_of(name) {
+        Some(input) => normalize_date(input, &date::today())
+                           .map(Some)
+                           .map_err(|e| {
+                               println!("{}", invalid_date_message(input, &e));
+                               Error::InputError
+                           }),
+        None => Ok(None)
+    }
+}
+
 #[cfg(test)]
 mod test {
     use clap::{ App, Arg };
     use std::path::PathBuf;
+    use serde_json;
     use super::{ Entry, Validation, filepath };
 
+    #[test]
+    fn line_and_json_carry_the_same_fields() {
+        let entry = Entry::new("2016-03-01", "1250.00").with_note("car | repair\nnext day")
+                                                        .with_tags(&["car", "auto"])
+                                                        .with_reconciled();
+        let line = entry.to_string();
+        let json = serde_json::to_string(&entry).unwrap();
+
+        assert_eq!(line, "2016-03-01|1250.00|note=car \\| repair\\nnext day|tags=car,auto|reconciled\n");
+        assert_eq!(json, "{\"date\":\"2016-03-01\",\"amount\":\"1250.00\",\"note\":\"car | repair\\nnext day\",\
+                          \"tags\":[\"car\",\"auto\"],\"reconciled\":true}");
+        assert_eq!(Entry::from_line(line.trim_end()), entry);
+        assert_eq!(serde_json::from_str::<Entry>(&json).unwrap(), entry);
+        assert_eq!(Entry::new("2016-03-01", "1200.00").differences(&entry), vec!["amount", "note", "tags", "reconciled"]);
+
+        assert!(serde_json::from_str::<Entry>("{\"date\": \"2016-03-01\"}").is_err());
+        assert!(serde_json::from_str::<Entry>("{\"date\": \"2016-03-01\", \"amount\": \"1.00\", \"tags\": \"car\"}").is_err());
+        assert_eq!(serde_json::from_str::<Entry>("{\"date\": \"2016-03-01\", \"amount\": \"1.00\", \"bank\": \"x\"}").unwrap(),
+                   Entry::new("2016-03-01", "1.00"));
+    }
+
     #[test]
     fn validate_date_string() {
         let valid_date = "2016-09-01";
@@ -125,6 +335,8 @@ mod test {
 
         assert_eq!(valid_entry.validate(), Validation::Valid);
         assert_eq!(invalid_entry.validate(), Validation::AmountParseError);
+        assert_eq!(Entry::new(valid_date, "NaN").validate(), Validation::AmountParseError);
+        assert_eq!(Entry::new(valid_date, "1e3").validate(), Validation::AmountParseError);
     }
 
     #[test]
@@ -147,6 +359,37 @@ mod test {
         assert_eq!(entry.amount_string, "1000.00");
     }
 
+    #[test]
+    fn entry_round_trips_notes_and_tags() {
+        let entry = Entry::new("2016-03-05", "1250.00").with_note("car repair | tow\nand \\ tip")
+                                                         .with_tags(&["car", "auto"]);
+        let line = format!("{}", entry);
+
+        assert_eq!(line, "2016-03-05|1250.00|note=car repair \\| tow\\nand \\\\ tip|tags=car,auto\n");
+        assert_eq!(Entry::from_line(line.trim_end()), entry);
+        assert_eq!(Entry::from_line("2016-03-05|1250.00|tags=car|future=field"),
+                   Entry::new("2016-03-05", "1250.00").with_tags(&["car"]));
+    }
+
+    #[test]
+    fn entry_round_trips_reconciled_marker() {
+        let entry = Entry::new("2016-03-31", "1250.00").with_tags(&["statement"])
+                                                         .with_reconciled();
+        let line = format!("{}", entry);
+
+        assert_eq!(line, "2016-03-31|1250.00|tags=statement|reconciled\n");
+        assert_eq!(Entry::from_line(line.trim_end()), entry);
+    }
+
+    #[test]
+    fn validate_tags() {
+        let entry = Entry::new("2016-09-01", "1000");
+
+        assert_eq!(entry.clone().with_tags(&["car"]).validate(), Validation::Valid);
+        assert_eq!(entry.clone().with_tags(&["car repair"]).validate(), Validation::TagParseError);
+        assert_eq!(entry.with_tags(&["a,b"]).validate(), Validation::TagParseError);
+    }
+
     #[test]
     fn returns_default_filepath() {
         let matches = App::new("test")
@@ -0,0 +1,231 @@
+extern crate clap;
+
+use std::env;
+use std::path::Path;
+use std::str::FromStr;
+use clap::ArgMatches;
+use crossterm::terminal;
+
+use amount::canonical;
+use balance::{ Interpolation, balance_on };
+use base::{ Entry, Error, date_value };
+use crypt;
+use date::{ days_between, shift_date };
+use show::{ delta_by_line, read_selected };
+
+const SPARKS: [char; 8] = ['▁', '▂', '▃', '▄', '▅', '▆', '▇', '█'];
+const DEFAULT_WIDTH: usize = 80;
+const DEFAULT_HEIGHT: usize = 10;
+
+/// Which of `steps` even bands of `min..=max` a value falls in, from 0.
+fn scale(value: f64, min: f64, max: f64, steps: usize) -> usize {
+    if max <= min || steps < 2 {
+        return steps / 2
+    }
+    ((value - min) / (max - min) * (steps - 1) as f64).round() as usize
+}
+
+fn bounds<I>(values: I) -> (f64, f64)
+    where I: Iterator<Item = f64> {
+    values.fold((f64::INFINITY, f64::NEG_INFINITY), |(min, max), v| (min.min(v), max.max(v)))
+}
+
+/// One block per entry, the most recent `width` entries.
+pub fn sparkline(entries: &[Entry], width: usize) -> String {
+    let shown = &entries[entries.len().saturating_sub(width)..];
+    let (min, max) = bounds(shown.iter().map(|e| e.amount()));
+    shown.iter()
+         .map(|e| SPARKS[scale(e.amount(), min, max, SPARKS.len())])
+         .collect()
+}
+
+/// Draws `grid` with labels to its left, a horizontal axis along row
+/// `axis`, and the first and last dates below.
+fn frame(grid: Vec<Vec<char>>, labels: &[(usize, String)], label_width: usize, axis: usize, first: &str, last: &str) -> String {
+    let plot = grid.first().map_or(0, |row| row.len());
+    let mut out = String::new();
+    for (r, row) in grid.iter().enumerate() {
+        let label = labels.iter().find(|(l, _)| *l == r).map_or("", |(_, text)| text.as_str());
+        let edge = match (r == axis, r + 1 == grid.len(), label.is_empty()) {
+            (true, true, _) => '└',
+            (true, false, _) => '┼',
+            (false, _, false) => '┤',
+            (false, _, true) => '│'
+        };
+        let cells = row.iter()
+                       .map(|&c| if r == axis && c == ' ' { '─' } else { c })
+                       .collect::<String>();
+        out.push_str(format!("{:>width$} {}{}", label, edge, cells, width = label_width).trim_end());
+        out.push('\n');
+    }
+    let gap = plot.saturating_sub(first.len()).max(last.len() + 1);
+    out.push_str(&format!("{:width$}  {}{:>gap$}\n", "", first, last, width = label_width, gap = gap));
+    out
+}
+
+fn label_width(labels: &[(usize, String)]) -> usize {
+    labels.iter().map(|(_, l)| l.len()).max().unwrap_or(0)
+}
+
+/// Plots the balance against time, so that uneven gaps between entries
+/// show as such; columns between entries are estimated on a straight line.
+pub fn line_chart(entries: &[Entry], width: usize, height: usize) -> String {
+    let (min, max) = bounds(entries.iter().map(|e| e.amount()));
+    let labels = vec![(0, canonical(max)), (height - 1, canonical(min))];
+    let label_width = label_width(&labels);
+    let plot = width.saturating_sub(label_width + 2).max(2);
+    let first = &entries[0].date_string;
+    let last = &entries[entries.len() - 1].date_string;
+    let span = days_between(first, last).unwrap_or(0);
+
+    let rows = (0..plot).map(|x| {
+                            let offset = span * x as i64 / (plot - 1) as i64;
+                            let date = shift_date(first, 0, offset).unwrap_or_else(|| first.clone());
+                            let value = balance_on(entries, &date, Interpolation::Linear).map_or(min, |b| b.amount);
+                            height - 1 - scale(value, min, max, height)
+                        })
+                        .collect::<Vec<usize>>();
+    let mut grid = vec![vec![' '; plot]; height + 1];
+    for (x, &row) in rows.iter().enumerate() {
+        if x > 0 {
+            let before = rows[x - 1];
+            for line in grid.iter_mut().take(row.max(before)).skip(row.min(before) + 1) {
+                line[x] = '│';
+            }
+        }
+        grid[row][x] = '•';
+    }
+    frame(grid, &labels, label_width, height, first, last)
+}
+
+/// Bars of each step's change, rising above a zero axis for increases and
+/// hanging below it for decreases.
+pub fn delta_bars(entries: &[Entry], width: usize, height: usize) -> String {
+    let deltas = delta_by_line(entries);
+    let (low, high) = bounds(deltas.iter().map(|d| d.delta()).chain(Some(0.0)));
+    let above = if high - low > 0.0 { (height as f64 * high / (high - low)).round() as usize } else { height };
+    let below = height - above;
+    let mut labels = vec![(above, "0".to_string())];
+    if above > 0 {
+        labels.push((0, canonical(high)));
+    }
+    if below > 0 {
+        labels.push((height, canonical(low)));
+    }
+    let label_width = label_width(&labels);
+    let plot = width.saturating_sub(label_width + 2).max(1);
+
+    let shown = &deltas[deltas.len().saturating_sub(plot)..];
+    let column = plot / shown.len();
+    let bar = if column > 1 { column - 1 } else { 1 };
+    let mut grid = vec![vec![' '; plot]; height + 1];
+    for (i, delta) in shown.iter().enumerate() {
+        let value = delta.delta();
+        let rows = if value > 0.0 {
+            let size = (value / high * above as f64).round() as usize;
+            above - size..above
+        } else if value < 0.0 {
+            let size = (value / low * below as f64).round() as usize;
+            above + 1..above + 1 + size
+        } else {
+            0..0
+        };
+        for row in rows {
+            for cell in &mut grid[row][i * column..i * column + bar] {
+                *cell = '█';
+            }
+        }
+    }
+    let first = &entries[entries.len() - shown.len() - 1].date_string;
+    let last = &entries[entries.len() - 1].date_string;
+    frame(grid, &labels, label_width, above, first, last)
+}
+
+/// `--width`, then the width of the terminal, then the `COLUMNS` the shell
+/// exports when output is not going to one, then 80.
+fn chart_width(submatches: &ArgMatches) -> Result<usize, Error> {
+    match submatches.value_of("width") {
+        Some(width) => usize::from_str(width).map_err(|_| {
+            println!("Invalid Width {}; must be a number of columns", width);
+            Error::InputError
+        }),
+        None => Ok(terminal::size().ok()
+                                   .map(|(columns, _)| columns as usize)
+                                   .filter(|&columns| columns > 0)
+                                   .or_else(|| env::var("COLUMNS").ok().and_then(|c| usize::from_str(&c).ok()))
+                                   .unwrap_or(DEFAULT_WIDTH))
+    }
+}
+
+pub fn run_chart(data_path: &Path, matches: &ArgMatches) -> Result<bool, Error> {
+    let submatches = matches.subcommand_matches("chart").ok_or(Error::InputError)?;
+    date_value(submatches, "date")?;
+    let width = chart_width(submatches)?;
+    let height = submatches.value_of("height")
+                           .map_or(Ok(DEFAULT_HEIGHT), usize::from_str)
+                           .map_err(|_| Error::InputError)?
+                           .max(2);
+    let entries = &read_selected(data_path, submatches).map_err(crypt::read_error)?;
+    if entries.len() < 2 {
+        println!("Need at least two entries to chart");
+        return Ok(false)
+    }
+
+    if submatches.is_present("sparkline") {
+        let first = &entries[0].date_string;
+        let last = &entries[entries.len() - 1].date_string;
+        let line = sparkline(entries, width.saturating_sub(first.len() + last.len() + 2));
+        println!("{} {} {}", first, line, last);
+    } else if submatches.is_present("deltas") {
+        print!("{}", delta_bars(entries, width, height));
+    } else {
+        print!("{}", line_chart(entries, width, height));
+    }
+    Ok(true)
+}
+
+#[cfg(test)]
+mod test {
+    use base::Entry;
+    use super::{ sparkline, line_chart, delta_bars };
+
+    fn entries() -> Vec<Entry> {
+        vec![Entry::new("2016-01-01", "1000.00"),
+             Entry::new("2016-01-11", "1200.00"),
+             Entry::new("2016-01-21", "900.00"),
+             Entry::new("2016-01-31", "1300.00")
+            ]
+    }
+
+    #[test]
+    fn sparkline_scales_to_blocks() {
+        assert_eq!(sparkline(&entries(), 80), "▃▆▁█");
+        assert_eq!(sparkline(&entries(), 2), "▁█");
+    }
+
+    #[test]
+    fn line_chart_plots_balance_over_time() {
+        let expected = ["1300.00 ┤           •",
+                        "        │  ••••    •",
+                        "        │••    •  •",
+                        " 900.00 ┤       ••",
+                        "        └────────────",
+                        "         2016-01-01 2016-01-31",
+                        ""];
+
+        assert_eq!(line_chart(&entries(), 21, 4), expected.join("\n"));
+    }
+
+    #[test]
+    fn delta_bars_hang_decreases_below_axis() {
+        let expected = [" 400.00 ┤    █",
+                        "        │█   █",
+                        "      0 ┼──────",
+                        "        │  █",
+                        "-300.00 ┤  █",
+                        "         2016-01-01 2016-01-31",
+                        ""];
+
+        assert_eq!(delta_bars(&entries(), 15, 4), expected.join("\n"));
+    }
+}
@@ -0,0 +1,199 @@
+use std::fmt;
+use std::fs::{ File, OpenOptions };
+use std::io::{ Read, Write };
+use std::path::{ Path, PathBuf };
+use clap::ArgMatches;
+
+use base::Error;
+
+/// Settings kept beside the ledger in an INI-style file:
+///
+/// ```text
+/// # comments are kept when the file is rewritten
+/// [envelopes]
+/// groceries = 600 rollover
+/// ```
+///
+/// Lines are kept as written so that `set` only touches the line it changes.
+#[derive(PartialEq, Eq, Debug, Default)]
+pub struct Config {
+    lines: Vec<String>
+}
+
+impl Config {
+    pub fn parse(contents: &str) -> Self {
+        Config { lines: contents.lines().map(|l| l.to_string()).collect() }
+    }
+
+    pub fn read(path: &Path) -> Result<Self, Error> {
+        let mut contents = String::new();
+        match File::open(path) {
+            Ok(mut f) => f.read_to_string(&mut contents)
+                          .map(|_| Config::parse(&contents))
+                          .map_err(|_| Error::ReadError),
+            Err(_) => Ok(Config::default())
+        }
+    }
+
+    pub fn write(&self, path: &Path) -> Result<bool, Error> {
+        OpenOptions::new()
+                    .write(true)
+                    .truncate(true)
+                    .create(true)
+                    .open(path)
+                    .and_then(|mut f| f.write_all(format!("{}", self).as_bytes()))
+                    .map(|_| true)
+                    .map_err(|_| Error::WriteError)
+    }
+
+    /// Names of every section, in file order.
+    pub fn sections(&self) -> Vec<String> {
+        self.lines.iter()
+                  .filter_map(|line| section_name(line))
+                  .collect()
+    }
+
+    /// Key and value pairs of a section, in file order.
+    pub fn section(&self, name: &str) -> Vec<(String, String)> {
+        let mut in_section = false;
+        let mut pairs = Vec::new();
+        for line in &self.lines {
+            if let Some(section) = section_name(line) {
+                in_section = section == name;
+            } else if in_section {
+                if let Some(pair) = key_value(line) {
+                    pairs.push(pair);
+                }
+            }
+        }
+        pairs
+    }
+
+    /// Replaces `key` in `section`, or adds it, creating the section as needed.
+    pub fn set(&mut self, section: &str, key: &str, value: &str) {
+        let assignment = format!("{} = {}", key, value);
+        let mut in_section = false;
+        let mut insert_at = None;
+        for i in 0..self.lines.len() {
+            if let Some(name) = section_name(&self.lines[i]) {
+                if in_section {
+                    break
+                }
+                in_section = name == section;
+                if in_section {
+                    insert_at = Some(i + 1);
+                }
+            } else if in_section {
+                match key_value(&self.lines[i]) {
+                    Some((ref k, _)) if k == key => {
+                        self.lines[i] = assignment;
+                        return
+                    },
+                    Some(_) => insert_at = Some(i + 1),
+                    None => {}
+                }
+            }
+        }
+        match insert_at {
+            Some(i) => self.lines.insert(i, assignment),
+            None => {
+                if self.lines.last().is_some_and(|l| !l.trim().is_empty()) {
+                    self.lines.push(String::new());
+                }
+                self.lines.push(format!("[{}]", section));
+                self.lines.push(assignment);
+            }
+        }
+    }
+}
+
+impl fmt::Display for Config {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for line in &self.lines {
+            writeln!(f, "{}", line)?;
+        }
+        Ok(())
+    }
+}
+
+fn section_name(line: &str) -> Option<String> {
+    let trimmed = line.trim();
+    if trimmed.starts_with('[') && trimmed.ends_with(']') {
+        Some(trimmed[1..trimmed.len() - 1].trim().to_string())
+    } else {
+        None
+    }
+}
+
+fn key_value(line: &str) -> Option<(String, String)> {
+    let trimmed = line.trim();
+    if trimmed.starts_with('#') || trimmed.starts_with(';') {
+        return None
+    }
+    trimmed.split_once('=')
+           .map(|(k, v)| (k.trim().to_string(), v.trim().to_string()))
+}
+
+/// The config file given with `--config`, or the ledger path with `.conf`
+/// appended, so `~/.budget` is configured by `~/.budget.conf`.
+pub fn config_path(matches: &ArgMatches, data_path: &Path) -> PathBuf {
+    match matches.value_of("config") {
+        Some(path) => PathBuf::from(path),
+        None => default_config_path(data_path)
+    }
+}
+
+pub fn default_config_path(data_path: &Path) -> PathBuf {
+    let mut path = data_path.as_os_str().to_owned();
+    path.push(".conf");
+    PathBuf::from(path)
+}
+
+#[cfg(test)]
+mod test {
+    use super::Config;
+
+    const CONTENTS: &str = "# household\n\
+                            [envelopes]\n\
+                            groceries = 600 rollover\n\
+                            ; dining is new\n\
+                            dining=200\n\
+                            \n\
+                            [recurring paycheck]\n\
+                            amount = 2000\n";
+
+    #[test]
+    fn reads_sections_and_values() {
+        let config = Config::parse(CONTENTS);
+
+        assert_eq!(config.sections(), vec!["envelopes", "recurring paycheck"]);
+        assert_eq!(config.section("envelopes"),
+                   vec![("groceries".to_string(), "600 rollover".to_string()),
+                        ("dining".to_string(), "200".to_string())]);
+        assert_eq!(config.section("recurring paycheck"),
+                   vec![("amount".to_string(), "2000".to_string())]);
+        assert_eq!(config.section("recurring rent"), vec![]);
+    }
+
+    #[test]
+    fn sets_values_in_place() {
+        let mut config = Config::parse(CONTENTS);
+        config.set("envelopes", "dining", "250");
+        config.set("envelopes", "gas", "100");
+        config.set("recurring rent", "amount", "-1200");
+
+        assert_eq!(format!("{}", config),
+                   "# household\n\
+                    [envelopes]\n\
+                    groceries = 600 rollover\n\
+                    ; dining is new\n\
+                    dining = 250\n\
+                    gas = 100\n\
+                    \n\
+                    [recurring paycheck]\n\
+                    amount = 2000\n\
+                    \n\
+                    [recurring rent]\n\
+                    amount = -1200\n");
+    }
+}
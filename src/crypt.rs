@@ -0,0 +1,425 @@
+extern crate clap;
+
+use std::env;
+use std::error;
+use std::fmt;
+use std::fs::{ self, File, OpenOptions };
+use std::io::{ self, ErrorKind, Read, Write };
+use std::path::{ Path, PathBuf };
+use std::sync::Mutex;
+use argon2::{ Algorithm, Argon2, Params, Version };
+use atty::{ self, Stream };
+use chacha20poly1305::{ KeyInit, XChaCha20Poly1305, XNonce };
+use chacha20poly1305::aead::{ Aead, Payload };
+use clap::ArgMatches;
+
+use base::Error;
+use index::index_path;
+use journal::journal_path;
+use storage;
+
+/// Encrypted files start with the magic and a format version, then the
+/// Argon2id costs, salt and nonce needed to open them. The whole header is
+/// authenticated along with the ciphertext that follows it.
+const MAGIC: &[u8; 7] = b"%BUDGET";
+const VERSION: u8 = 1;
+const SALT_LEN: usize = 16;
+const NONCE_LEN: usize = 24;
+const HEADER_LEN: usize = MAGIC.len() + 1 + 12 + SALT_LEN + NONCE_LEN;
+
+const PASSPHRASE_VAR: &str = "BUDGET_PASSPHRASE";
+
+/// The passphrase is asked for once per run, and the key derived from it
+/// kept for as long as the salt and costs match, since Argon2 is slow on
+/// purpose and a command may read the ledger several times.
+static PASSPHRASE: Mutex<Option<String>> = Mutex::new(None);
+static KEY: Mutex<Option<DerivedKey>> = Mutex::new(None);
+
+struct DerivedKey {
+    header: Vec<u8>,
+    passphrase: String,
+    key: [u8; 32]
+}
+
+/// A ciphertext that fails authentication: the passphrase is wrong or the
+/// file was altered, and there is no telling which.
+#[derive(Debug)]
+pub struct WrongPassphrase;
+
+impl fmt::Display for WrongPassphrase {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "wrong passphrase, or the file has been altered")
+    }
+}
+
+impl error::Error for WrongPassphrase {}
+
+pub fn is_wrong_passphrase(e: &io::Error) -> bool {
+    e.get_ref().is_some_and(|inner| inner.is::<WrongPassphrase>())
+}
+
+/// `Error::PassphraseError` for a file that would not decrypt or had no
+/// passphrase to hand, reported as such; `Error::ReadError` for anything
+/// else.
+pub fn read_error(e: io::Error) -> Error {
+    if is_wrong_passphrase(&e) {
+        println!("Could not decrypt the ledger: {}", WrongPassphrase);
+        Error::PassphraseError
+    } else if e.kind() == ErrorKind::InvalidInput {
+        println!("{}", e);
+        Error::PassphraseError
+    } else {
+        Error::ReadError
+    }
+}
+
+pub fn write_error(e: io::Error) -> Error {
+    match read_error(e) {
+        Error::PassphraseError => Error::PassphraseError,
+        _ => Error::WriteError
+    }
+}
+
+fn invalid(message: &str) -> io::Error {
+    io::Error::new(ErrorKind::InvalidData, message.to_string())
+}
+
+/// Argon2id costs: memory in KiB, iterations and lanes.
+#[derive(PartialEq, Eq, Debug, Clone, Copy)]
+pub struct Costs {
+    pub memory: u32,
+    pub iterations: u32,
+    pub lanes: u32
+}
+
+impl Default for Costs {
+    fn default() -> Self {
+        Costs { memory: Params::DEFAULT_M_COST, iterations: Params::DEFAULT_T_COST, lanes: Params::DEFAULT_P_COST }
+    }
+}
+
+struct Header {
+    costs: Costs,
+    salt: [u8; SALT_LEN],
+    nonce: [u8; NONCE_LEN]
+}
+
+impl Header {
+    fn new(costs: Costs, salt: [u8; SALT_LEN]) -> io::Result<Self> {
+        let mut nonce = [0; NONCE_LEN];
+        getrandom::getrandom(&mut nonce).map_err(|e| io::Error::other(e.to_string()))?;
+        Ok(Header { costs, salt, nonce })
+    }
+
+    fn parse(bytes: &[u8]) -> io::Result<Self> {
+        if !is_sealed(bytes) || bytes.len() < HEADER_LEN {
+            return Err(invalid("not an encrypted ledger"))
+        }
+        if bytes[MAGIC.len()] != VERSION {
+            return Err(invalid(&format!("unsupported encryption version {}", bytes[MAGIC.len()])))
+        }
+        let word = |at: usize| {
+            let start = MAGIC.len() + 1 + at * 4;
+            u32::from_le_bytes([bytes[start], bytes[start + 1], bytes[start + 2], bytes[start + 3]])
+        };
+        let mut salt = [0; SALT_LEN];
+        let mut nonce = [0; NONCE_LEN];
+        salt.copy_from_slice(&bytes[MAGIC.len() + 13..MAGIC.len() + 13 + SALT_LEN]);
+        nonce.copy_from_slice(&bytes[HEADER_LEN - NONCE_LEN..HEADER_LEN]);
+        Ok(Header { costs: Costs { memory: word(0), iterations: word(1), lanes: word(2) }, salt, nonce })
+    }
+
+    fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = MAGIC.to_vec();
+        bytes.push(VERSION);
+        for word in &[self.costs.memory, self.costs.iterations, self.costs.lanes] {
+            bytes.extend_from_slice(&word.to_le_bytes());
+        }
+        bytes.extend_from_slice(&self.salt);
+        bytes.extend_from_slice(&self.nonce);
+        bytes
+    }
+
+    fn key(&self, passphrase: &str) -> io::Result<[u8; 32]> {
+        let id = self.to_bytes()[..HEADER_LEN - NONCE_LEN].to_vec();
+        let mut cached = KEY.lock().unwrap();
+        if let Some(ref derived) = *cached {
+            if derived.header == id && derived.passphrase == passphrase {
+                return Ok(derived.key)
+            }
+        }
+        let params = Params::new(self.costs.memory, self.costs.iterations, self.costs.lanes, Some(32))
+                            .map_err(|e| invalid(&e.to_string()))?;
+        let mut key = [0; 32];
+        Argon2::new(Algorithm::Argon2id, Version::V0x13, params)
+               .hash_password_into(passphrase.as_bytes(), &self.salt, &mut key)
+               .map_err(|e| invalid(&e.to_string()))?;
+        *cached = Some(DerivedKey { header: id, passphrase: passphrase.to_string(), key });
+        Ok(key)
+    }
+}
+
+fn is_sealed(bytes: &[u8]) -> bool {
+    bytes.starts_with(MAGIC)
+}
+
+/// Whether the file at `path` is encrypted; a missing file is not. Only
+/// the start of the file is read.
+pub fn is_encrypted(path: &Path) -> bool {
+    let mut start = Vec::new();
+    File::open(path).and_then(|f| f.take(MAGIC.len() as u64).read_to_end(&mut start))
+                    .map(|_| is_sealed(&start))
+                    .unwrap_or(false)
+}
+
+fn seal_with(header: Header, contents: &str, passphrase: &str) -> io::Result<Vec<u8>> {
+    let key = header.key(passphrase)?;
+    let mut bytes = header.to_bytes();
+    let ciphertext = XChaCha20Poly1305::new(&key.into())
+                                       .encrypt(XNonce::from_slice(&header.nonce),
+                                                Payload { msg: contents.as_bytes(), aad: &bytes })
+                                       .map_err(|_| invalid("encryption failed"))?;
+    bytes.extend(ciphertext);
+    Ok(bytes)
+}
+
+/// Encrypts `contents` under a key derived from `passphrase` with a fresh
+/// salt.
+pub fn seal(contents: &str, passphrase: &str, costs: Costs) -> io::Result<Vec<u8>> {
+    let mut salt = [0; SALT_LEN];
+    getrandom::getrandom(&mut salt).map_err(|e| io::Error::other(e.to_string()))?;
+    seal_with(Header::new(costs, salt)?, contents, passphrase)
+}
+
+pub fn open(bytes: &[u8], passphrase: &str) -> io::Result<String> {
+    let header = Header::parse(bytes)?;
+    let key = header.key(passphrase)?;
+    let plaintext = XChaCha20Poly1305::new(&key.into())
+                                      .decrypt(XNonce::from_slice(&header.nonce),
+                                               Payload { msg: &bytes[HEADER_LEN..], aad: &bytes[..HEADER_LEN] })
+                                      .map_err(|_| io::Error::new(ErrorKind::InvalidData, WrongPassphrase))?;
+    String::from_utf8(plaintext).map_err(|_| invalid("decrypted ledger is not text"))
+}
+
+/// The passphrase from `BUDGET_PASSPHRASE`, or asked for on the terminal.
+fn passphrase(path: &Path) -> io::Result<String> {
+    let mut cached = PASSPHRASE.lock().unwrap();
+    if let Some(ref passphrase) = *cached {
+        return Ok(passphrase.clone())
+    }
+    let passphrase = match env::var(PASSPHRASE_VAR) {
+        Ok(passphrase) => passphrase,
+        Err(_) if atty::is(Stream::Stdin) => rpassword::prompt_password(format!("Passphrase for {}: ", path.display()))?,
+        Err(_) => return Err(io::Error::new(ErrorKind::InvalidInput,
+                                            format!("{} is encrypted; set {}", path.display(), PASSPHRASE_VAR)))
+    };
+    *cached = Some(passphrase.clone());
+    Ok(passphrase)
+}
+
+/// Text from `bytes` as read from a ledger file or an old version of one,
+/// decrypting it if need be.
+pub fn decode(bytes: Vec<u8>, path: &Path) -> io::Result<String> {
+    if is_sealed(&bytes) {
+        open(&bytes, &passphrase(path)?)
+    } else {
+        String::from_utf8(bytes).map_err(|_| invalid("ledger is not text"))
+    }
+}
+
+/// Reads a ledger file, decrypting it if it is encrypted.
+pub fn read(path: &Path) -> io::Result<String> {
+    decode(fs::read(path)?, path)
+}
+
+/// Writes `bytes` beside the file and renames them over it, so that a
+/// failed or interrupted write leaves the old version whole and a reader
+/// never sees it half written.
+fn replace(path: &Path, bytes: &[u8]) -> io::Result<()> {
+    let mut temporary = path.as_os_str().to_owned();
+    temporary.push(".tmp");
+    let mut file = File::create(&temporary)?;
+    file.write_all(bytes)?;
+    file.sync_all()?;
+    fs::rename(&temporary, path)
+}
+
+/// Replaces the file with `contents`, encrypted if `encrypt`, by way of
+/// `replace` either way. An encrypted file is opened first, so that a
+/// wrong passphrase cannot overwrite it, and keeps its salt and costs.
+pub fn write(path: &Path, contents: &str, encrypt: bool) -> io::Result<()> {
+    if !encrypt {
+        return replace(path, contents.as_bytes())
+    }
+    let passphrase = passphrase(path)?;
+    let existing = fs::read(path).ok().filter(|bytes| is_sealed(bytes));
+    let sealed = match existing {
+        Some(bytes) => {
+            open(&bytes, &passphrase)?;
+            let header = Header::parse(&bytes)?;
+            seal_with(Header::new(header.costs, header.salt)?, contents, &passphrase)?
+        },
+        None => seal(contents, &passphrase, Costs::default())?
+    };
+    replace(path, &sealed)
+}
+
+/// Adds `text` to the end of the file, encrypting the whole of it if
+/// `encrypt`; a plain file is appended to in place.
+pub fn append(path: &Path, text: &str, encrypt: bool) -> io::Result<()> {
+    if !encrypt {
+        return OpenOptions::new()
+                           .append(true)
+                           .create(true)
+                           .open(path)
+                           .and_then(|mut f| f.write_all(text.as_bytes()))
+    }
+    let existing = match read(path) {
+        Err(ref e) if e.kind() == ErrorKind::NotFound => String::new(),
+        existing => existing?
+    };
+    write(path, &(existing + text), true)
+}
+
+/// A passphrase for a file being encrypted, entered twice on a terminal.
+fn new_passphrase() -> io::Result<String> {
+    if let Ok(passphrase) = env::var(PASSPHRASE_VAR) {
+        return Ok(passphrase)
+    }
+    if !atty::is(Stream::Stdin) {
+        return Err(io::Error::new(ErrorKind::InvalidInput, format!("set {} to encrypt", PASSPHRASE_VAR)))
+    }
+    let passphrase = rpassword::prompt_password("New passphrase: ")?;
+    if passphrase.is_empty() || rpassword::prompt_password("Repeat passphrase: ")? != passphrase {
+        return Err(io::Error::new(ErrorKind::InvalidInput, "passphrases are empty or do not match"))
+    }
+    Ok(passphrase)
+}
+
+/// The ledger and, when there is one, its journal.
+fn files(data_path: &Path) -> Vec<PathBuf> {
+    let journal = journal_path(data_path);
+    let mut files = vec![data_path.to_path_buf()];
+    if journal.exists() {
+        files.push(journal);
+    }
+    files
+}
+
+pub fn run_encrypt(data_path: &Path, matches: &ArgMatches) -> Result<bool, Error> {
+    matches.subcommand_matches("encrypt").ok_or(Error::InputError)?;
+    if is_encrypted(data_path) {
+        println!("{} is already encrypted", data_path.display());
+        return Ok(false)
+    }
+    if storage::is_sqlite(data_path) {
+        println!("Only text ledgers can be encrypted; {} is SQLite", data_path.display());
+        return Err(Error::InputError)
+    }
+    let passphrase = new_passphrase().map_err(|e| {
+        println!("{}", e);
+        Error::InputError
+    })?;
+    *PASSPHRASE.lock().unwrap() = Some(passphrase);
+    for path in files(data_path) {
+        let contents = read(&path).map_err(read_error)?;
+        write(&path, &contents, true).map_err(write_error)?;
+        println!("Encrypted {}", path.display());
+    }
+    // the index holds the last date in the clear
+    let _ = fs::remove_file(index_path(data_path));
+    Ok(true)
+}
+
+pub fn run_decrypt(data_path: &Path, matches: &ArgMatches) -> Result<bool, Error> {
+    matches.subcommand_matches("decrypt").ok_or(Error::InputError)?;
+    if !is_encrypted(data_path) {
+        println!("{} is not encrypted", data_path.display());
+        return Ok(false)
+    }
+    for path in files(data_path) {
+        let contents = read(&path).map_err(read_error)?;
+        write(&path, &contents, false).map_err(write_error)?;
+        println!("Decrypted {}", path.display());
+    }
+    Ok(true)
+}
+
+#[cfg(test)]
+mod test {
+    use std::env;
+    use std::fs;
+    use std::io::Read;
+    use show::read_file;
+    use base::Entry;
+    use super::{ Costs, PASSPHRASE, append, is_encrypted, is_wrong_passphrase, open, read, seal, write };
+
+    const LEDGER: &str = "2016-01-01|1000.00\n2016-02-01|1200.00\n";
+
+    // Small enough costs that the tests stay fast.
+    fn costs() -> Costs {
+        Costs { memory: 64, iterations: 1, lanes: 1 }
+    }
+
+    #[test]
+    fn opens_what_it_seals() {
+        let sealed = seal(LEDGER, "correct horse", costs()).unwrap();
+
+        assert!(sealed.starts_with(b"%BUDGET\x01"));
+        assert!(!String::from_utf8_lossy(&sealed).contains("1000.00"));
+        assert_eq!(open(&sealed, "correct horse").unwrap(), LEDGER);
+    }
+
+    #[test]
+    fn rejects_a_wrong_passphrase_or_an_altered_file() {
+        let mut sealed = seal(LEDGER, "correct horse", costs()).unwrap();
+
+        assert!(is_wrong_passphrase(&open(&sealed, "battery staple").unwrap_err()));
+        let last = sealed.len() - 1;
+        sealed[last] ^= 1;
+        assert!(is_wrong_passphrase(&open(&sealed, "correct horse").unwrap_err()));
+    }
+
+    #[test]
+    fn rejects_an_unknown_version() {
+        let mut sealed = seal(LEDGER, "correct horse", costs()).unwrap();
+        sealed[7] = 2;
+
+        let error = open(&sealed, "correct horse").unwrap_err();
+        assert!(!is_wrong_passphrase(&error));
+        assert_eq!(error.to_string(), "unsupported encryption version 2");
+    }
+
+    #[test]
+    fn reads_and_appends_to_an_encrypted_ledger() {
+        let path = env::temp_dir().join(format!("budget_crypt_{}", std::process::id()));
+        *PASSPHRASE.lock().unwrap() = Some("correct horse".to_string());
+        fs::write(&path, seal(LEDGER, "correct horse", costs()).unwrap()).unwrap();
+
+        append(&path, "2016-03-01|900.00\n", is_encrypted(&path)).unwrap();
+        assert!(is_encrypted(&path));
+        assert_eq!(read_file(&path).unwrap().last(), Some(&Entry::new("2016-03-01", "900.00")));
+
+        write(&path, &read(&path).unwrap(), false).unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap(), format!("{}2016-03-01|900.00\n", LEDGER));
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn replaces_a_plain_ledger_whole() {
+        let path = env::temp_dir().join(format!("budget_crypt_plain_{}", std::process::id()));
+        fs::write(&path, LEDGER).unwrap();
+        let mut reader = fs::File::open(&path).unwrap();
+
+        write(&path, "2016-03-01|900.00\n", false).unwrap();
+
+        // a reader that opened the old version still sees all of it
+        let mut old = String::new();
+        reader.read_to_string(&mut old).unwrap();
+        assert_eq!(old, LEDGER);
+        assert_eq!(fs::read_to_string(&path).unwrap(), "2016-03-01|900.00\n");
+        assert!(!env::temp_dir().join(format!("budget_crypt_plain_{}.tmp", std::process::id())).exists());
+        fs::remove_file(path).unwrap();
+    }
+}
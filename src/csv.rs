@@ -0,0 +1,186 @@
+extern crate time;
+
+use std::io::BufRead;
+
+use amount::normalize_amount;
+use base::Entry;
+use date::normalize_date;
+
+#[derive(PartialEq, Eq, Debug)]
+pub enum Column {
+    Name(String),
+    Index(usize)
+}
+
+impl Column {
+    /// Columns are given by header name or by 1-based position.
+    pub fn parse(input: &str) -> Self {
+        match input.parse::<usize>() {
+            Ok(n) if n > 0 => Column::Index(n - 1),
+            _ => Column::Name(input.to_string())
+        }
+    }
+
+    fn find(&self, header: &[String]) -> Option<usize> {
+        match *self {
+            Column::Index(i) => Some(i),
+            Column::Name(ref name) => header.iter()
+                                            .position(|h| h.trim().eq_ignore_ascii_case(name.trim()))
+        }
+    }
+}
+
+#[derive(PartialEq, Eq, Debug)]
+pub struct CsvMapping {
+    pub date_column: Column,
+    pub balance_column: Column,
+    pub date_format: Option<String>,
+    pub delimiter: char
+}
+
+impl CsvMapping {
+    fn has_header(&self) -> bool {
+        !matches!((&self.date_column, &self.balance_column), (&Column::Index(_), &Column::Index(_)))
+    }
+
+    fn date(&self, input: &str, today: &time::Tm) -> Option<String> {
+        match self.date_format {
+            Some(ref format) => time::strptime(input.trim(), format)
+                                    .ok()
+                                    .and_then(|tm| time::strftime("%Y-%m-%d", &tm).ok()),
+            None => normalize_date(input, today).ok()
+        }
+    }
+}
+
+/// Splits one CSV line, honoring double-quoted fields with `""` escapes.
+pub fn split_record(line: &str, delimiter: char) -> Vec<String> {
+    let mut fields = Vec::new();
+    let mut field = String::new();
+    let mut quoted = false;
+    let mut chars = line.trim_end_matches(['\r', '\n']).chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '"' if quoted && chars.peek() == Some(&'"') => { chars.next(); field.push('"') },
+            '"' => quoted = !quoted,
+            c if c == delimiter && !quoted => fields.push(field.split_off(0)),
+            c => field.push(c)
+        }
+    }
+    fields.push(field);
+    fields
+}
+
+/// Reads balance entries from CSV rows, returning the entries in date order
+/// along with a description of each row that could not be used.
+pub fn entries_from_csv<R>(reader: R, mapping: &CsvMapping, today: &time::Tm) -> (Vec<Entry>, Vec<String>)
+    where R: BufRead {
+    let mut rows = reader.lines()
+                         .map_while(Result::ok)
+                         .enumerate()
+                         .filter(|(_, line)| !line.trim().is_empty())
+                         .map(|(n, line)| (n + 1, split_record(&line, mapping.delimiter)));
+
+    let mut skipped = Vec::new();
+    let columns = if mapping.has_header() {
+        rows.next().and_then(|(_, header)| {
+            mapping.date_column.find(&header)
+                   .and_then(|d| mapping.balance_column.find(&header).map(|b| (d, b)))
+        })
+    } else {
+        mapping.date_column.find(&[]).and_then(|d| mapping.balance_column.find(&[]).map(|b| (d, b)))
+    };
+    let (date_index, balance_index) = match columns {
+        Some(columns) => columns,
+        None => return (Vec::new(), vec!["header: date or balance column not found".to_string()])
+    };
+
+    let mut entries = Vec::new();
+    for (line_number, fields) in rows {
+        let date = fields.get(date_index).and_then(|d| mapping.date(d, today));
+        let balance = fields.get(balance_index).and_then(|b| normalize_amount(b));
+        match (date, balance) {
+            (Some(date), Some(balance)) => entries.push(Entry::new(date, balance)),
+            (None, _) => skipped.push(format!("line {}: unreadable date {}", line_number,
+                                              fields.get(date_index).map_or("", |d| d.as_str()))),
+            (_, None) => skipped.push(format!("line {}: unreadable balance {}", line_number,
+                                              fields.get(balance_index).map_or("", |b| b.as_str())))
+        }
+    }
+
+    // bank exports are often newest first; a running balance is read oldest first
+    if entries.first().map(|e| &e.date_string) > entries.last().map(|e| &e.date_string) {
+        entries.reverse();
+    }
+    entries.sort_by(|a, b| a.date_string.cmp(&b.date_string));
+    (entries, skipped)
+}
+
+#[cfg(test)]
+mod test {
+    use std::fs::File;
+    use std::io::BufReader;
+    use super::time;
+    use base::Entry;
+    use super::{ Column, CsvMapping, split_record, entries_from_csv };
+
+    fn today() -> time::Tm {
+        time::strptime("2016-09-07", "%Y-%m-%d").unwrap()
+    }
+
+    #[test]
+    fn splits_quoted_fields() {
+        assert_eq!(split_record("2016-01-31,\"Pay, \"\"June\"\"\",\"1,000.00\"\r", ','),
+                   vec!["2016-01-31", "Pay, \"June\"", "1,000.00"]);
+        assert_eq!(split_record("a;b;;c", ';'), vec!["a", "b", "", "c"]);
+    }
+
+    #[test]
+    fn parses_columns_by_name_or_position() {
+        assert_eq!(Column::parse("Balance"), Column::Name("Balance".to_string()));
+        assert_eq!(Column::parse("3"), Column::Index(2));
+        assert_eq!(Column::parse("0"), Column::Name("0".to_string()));
+    }
+
+    #[test]
+    fn reads_entries_from_bank_export() {
+        // Posted Date,Description,Amount,Running Balance
+        // 02/29/2016,"Rent, March",-50.00,"$1,250.00"
+        // 02/29/2016,Groceries,-1000.00,"$1,300.00"
+        // 02/20/2016,Card hold,-12.00,pending
+        // ...newest first
+        let f = File::open("./test_data/import_bank_export.csv").unwrap();
+        let mapping = CsvMapping { date_column: Column::Name("posted date".to_string()),
+                                   balance_column: Column::Name("Running Balance".to_string()),
+                                   date_format: Some("%m/%d/%Y".to_string()),
+                                   delimiter: ','
+                                 };
+
+        let (entries, skipped) = entries_from_csv(BufReader::new(f), &mapping, &today());
+
+        assert_eq!(entries, vec![Entry::new("2016-01-15", "1500.00"),
+                                 Entry::new("2016-01-31", "2500.00"),
+                                 Entry::new("2016-02-15", "2300.00"),
+                                 Entry::new("2016-02-29", "1300.00"),
+                                 Entry::new("2016-02-29", "1250.00")
+                                ]);
+        assert_eq!(skipped, vec!["line 4: unreadable balance pending".to_string()]);
+    }
+
+    #[test]
+    fn reads_positional_columns_without_header() {
+        let rows = "2016-01-31;1.000,00\n2016-02-29;1.250,50\n";
+        let mapping = CsvMapping { date_column: Column::Index(0),
+                                   balance_column: Column::Index(1),
+                                   date_format: None,
+                                   delimiter: ';'
+                                 };
+
+        let (entries, skipped) = entries_from_csv(rows.as_bytes(), &mapping, &today());
+
+        assert_eq!(entries, vec![Entry::new("2016-01-31", "1000.00"),
+                                 Entry::new("2016-02-29", "1250.50")
+                                ]);
+        assert!(skipped.is_empty());
+    }
+}
@@ -0,0 +1,301 @@
+extern crate time;
+
+use std::fmt;
+
+const WEEKDAYS: [&str; 7] = ["sunday", "monday", "tuesday", "wednesday",
+                             "thursday", "friday", "saturday"];
+
+const MONTHS: [&str; 12] = ["january", "february", "march", "april", "may", "june", "july",
+                            "august", "september", "october", "november", "december"];
+
+#[derive(PartialEq, Eq, Debug)]
+pub enum DateParse {
+    Ambiguous(String),
+    Invalid
+}
+
+#[derive(PartialEq, Eq, Debug, Clone, Copy)]
+struct Day {
+    year: i64,
+    month: i64,
+    day: i64
+}
+
+impl Day {
+    fn new(year: i64, month: i64, day: i64) -> Option<Self> {
+        if !(1..=12).contains(&month) || day < 1 || day > days_in_month(year, month) {
+            return None
+        }
+        Some(Day { year, month, day })
+    }
+
+    fn parse(date: &str) -> Option<Self> {
+        let numbers = date.split('-')
+                          .map(|n| n.parse::<i64>().ok())
+                          .collect::<Option<Vec<i64>>>()?;
+        match numbers.as_slice() {
+            [year, month, day] => Day::new(*year, *month, *day),
+            _ => None
+        }
+    }
+
+    fn from_tm(tm: &time::Tm) -> Self {
+        Day { year: tm.tm_year as i64 + 1900,
+              month: tm.tm_mon as i64 + 1,
+              day: tm.tm_mday as i64
+            }
+    }
+
+    // days since 1970-01-01, after Howard Hinnant's days_from_civil
+    fn ordinal(&self) -> i64 {
+        let y = if self.month <= 2 { self.year - 1 } else { self.year };
+        let era = if y >= 0 { y } else { y - 399 } / 400;
+        let yoe = y - era * 400;
+        let mp = (self.month + 9) % 12;
+        let doy = (153 * mp + 2) / 5 + self.day - 1;
+        let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+        era * 146097 + doe - 719468
+    }
+
+    fn from_ordinal(days: i64) -> Self {
+        let z = days + 719468;
+        let era = if z >= 0 { z } else { z - 146096 } / 146097;
+        let doe = z - era * 146097;
+        let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+        let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+        let mp = (5 * doy + 2) / 153;
+        let day = doy - (153 * mp + 2) / 5 + 1;
+        let month = if mp < 10 { mp + 3 } else { mp - 9 };
+        let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+        Day { year, month, day }
+    }
+
+    // `None` past the four digit years the ledger can hold
+    fn plus_days(&self, n: i64) -> Option<Self> {
+        let days = self.ordinal().checked_add(n)?;
+        let (first, last) = (Day { year: 0, month: 1, day: 1 }.ordinal(), Day { year: 9999, month: 12, day: 31 }.ordinal());
+        if days < first || days > last {
+            return None
+        }
+        Some(Day::from_ordinal(days))
+    }
+
+    fn plus_months(&self, n: i64) -> Option<Self> {
+        let index = (self.year * 12 + (self.month - 1)).checked_add(n)?;
+        let (year, month) = (index.div_euclid(12), index.rem_euclid(12) + 1);
+        if !(0..=9999).contains(&year) {
+            return None
+        }
+        Some(Day { year, month, day: self.day.min(days_in_month(year, month)) })
+    }
+
+    // 0 is Sunday, matching time::Tm::tm_wday
+    fn weekday(&self) -> i64 {
+        (self.ordinal() + 4).rem_euclid(7)
+    }
+}
+
+impl fmt::Display for Day {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:04}-{:02}-{:02}", self.year, self.month, self.day)
+    }
+}
+
+fn days_in_month(year: i64, month: i64) -> i64 {
+    match month {
+        2 if year % 4 == 0 && (year % 100 != 0 || year % 400 == 0) => 29,
+        2 => 28,
+        4 | 6 | 9 | 11 => 30,
+        _ => 31
+    }
+}
+
+pub fn today() -> time::Tm {
+    time::now()
+}
+
+pub fn format_date(tm: &time::Tm) -> String {
+    format!("{}", Day::from_tm(tm))
+}
+
+/// Moves a `yyyy-mm-dd` date by whole months and then by days. Month steps
+/// keep the day of the month, or use the last day of a shorter month.
+pub fn shift_date(date: &str, months: i64, days: i64) -> Option<String> {
+    Day::parse(date).and_then(|day| day.plus_months(months))
+                    .and_then(|day| day.plus_days(days))
+                    .map(|day| format!("{}", day))
+}
+
+/// Days from `start` to `end`, negative when `end` is earlier.
+pub fn days_between(start: &str, end: &str) -> Option<i64> {
+    Some(Day::parse(end)?.ordinal() - Day::parse(start)?.ordinal())
+}
+
+/// Normalizes human date input to `yyyy-mm-dd`, relative to `today`.
+///
+/// Accepts `today`, `yesterday`, `tomorrow`, offsets such as `-3d`, `+2w`,
+/// `-1m` or `-1y`, `last friday`, year-first dates separated by `-`, `/` or
+/// `.`, and month names as in `Sep 1 2016` or `1 September 2016`. Numeric
+/// dates that do not start with the year are rejected as ambiguous.
+pub fn normalize_date(input: &str, today: &time::Tm) -> Result<String, DateParse> {
+    let today = Day::from_tm(today);
+    let lowered = input.trim().to_lowercase();
+    let words = lowered.split(|c: char| c.is_whitespace() || c == ',')
+                       .filter(|w| !w.is_empty())
+                       .collect::<Vec<&str>>();
+
+    let parsed = match words.as_slice() {
+        ["today"] => Some(today),
+        ["yesterday"] => today.plus_days(-1),
+        ["tomorrow"] => today.plus_days(1),
+        ["last", weekday] => find_name(&WEEKDAYS, weekday).and_then(|target| {
+            let back = (today.weekday() - target as i64 - 1).rem_euclid(7) + 1;
+            today.plus_days(-back)
+        }),
+        [offset] if offset.starts_with('-') || offset.starts_with('+') => relative(&today, offset),
+        [numeric] => return numeric_date(numeric),
+        [month, day, year] if find_name(&MONTHS, month).is_some() =>
+            named_date(year, month, day),
+        [day, month, year] if find_name(&MONTHS, month).is_some() =>
+            named_date(year, month, day),
+        _ => None
+    };
+
+    parsed.map(|day| format!("{}", day)).ok_or(DateParse::Invalid)
+}
+
+fn find_name(names: &[&str], word: &str) -> Option<usize> {
+    if word.len() < 3 {
+        return None
+    }
+    names.iter().position(|name| name.starts_with(word))
+}
+
+fn relative(today: &Day, offset: &str) -> Option<Day> {
+    let (at, unit) = offset.char_indices().last()?;
+    let n = offset[..at].parse::<i64>().ok()?;
+    match unit {
+        'd' => today.plus_days(n),
+        'w' => today.plus_days(n.checked_mul(7)?),
+        'm' => today.plus_months(n),
+        'y' => today.plus_months(n.checked_mul(12)?),
+        _ => None
+    }
+}
+
+fn named_date(year: &str, month: &str, day: &str) -> Option<Day> {
+    let month = find_name(&MONTHS, month)? as i64 + 1;
+    let year = year.parse::<i64>().ok().filter(|_| year.len() == 4)?;
+    let day = day.trim_end_matches(|c: char| c.is_alphabetic()).parse::<i64>().ok()?;
+    Day::new(year, month, day)
+}
+
+fn numeric_date(input: &str) -> Result<String, DateParse> {
+    let parts = input.split(['-', '/', '.'])
+                     .collect::<Vec<&str>>();
+    if parts.len() != 3 || parts.iter().any(|p| p.is_empty() || !p.chars().all(|c| c.is_ascii_digit())) {
+        return Err(DateParse::Invalid)
+    }
+    let numbers = parts.iter()
+                       .map(|p| p.parse::<i64>().unwrap_or(0))
+                       .collect::<Vec<i64>>();
+
+    if parts[0].len() == 4 {
+        return Day::new(numbers[0], numbers[1], numbers[2])
+                   .map(|day| format!("{}", day))
+                   .ok_or(DateParse::Invalid)
+    }
+
+    let year = match parts[2].len() {
+        2 => 2000 + numbers[2],
+        4 => numbers[2],
+        _ => return Err(DateParse::Invalid)
+    };
+    Day::new(year, numbers[0], numbers[1])
+        .or_else(|| Day::new(year, numbers[1], numbers[0]))
+        .map(|day| DateParse::Ambiguous(format!("{}", day)))
+        .map_or(Err(DateParse::Invalid), Err)
+}
+
+pub fn invalid_date_message(input: &str, error: &DateParse) -> String {
+    match *error {
+        DateParse::Ambiguous(ref suggestion) =>
+            format!("Ambiguous Date {}; did you mean {}? Use yyyy-mm-dd", input, suggestion),
+        DateParse::Invalid =>
+            format!("Invalid Date {}; use yyyy-mm-dd, today, yesterday, -3d, last friday or Sep 1 2016",
+                    input)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::time;
+    use super::{ DateParse, days_between, normalize_date, shift_date };
+
+    fn today() -> time::Tm {
+        // a Wednesday
+        time::strptime("2016-09-07", "%Y-%m-%d").unwrap()
+    }
+
+    #[test]
+    fn normalizes_named_days() {
+        assert_eq!(normalize_date("today", &today()), Ok("2016-09-07".to_string()));
+        assert_eq!(normalize_date("Yesterday", &today()), Ok("2016-09-06".to_string()));
+        assert_eq!(normalize_date("last friday", &today()), Ok("2016-09-02".to_string()));
+        assert_eq!(normalize_date("last wed", &today()), Ok("2016-08-31".to_string()));
+    }
+
+    #[test]
+    fn normalizes_relative_offsets() {
+        assert_eq!(normalize_date("-3d", &today()), Ok("2016-09-04".to_string()));
+        assert_eq!(normalize_date("-7d", &today()), Ok("2016-08-31".to_string()));
+        assert_eq!(normalize_date("+1w", &today()), Ok("2016-09-14".to_string()));
+        assert_eq!(normalize_date("-1m", &today()), Ok("2016-08-07".to_string()));
+        assert_eq!(normalize_date("-1y", &today()), Ok("2015-09-07".to_string()));
+        assert_eq!(normalize_date("-3x", &today()), Err(DateParse::Invalid));
+    }
+
+    #[test]
+    fn rejects_offsets_with_other_units_or_out_of_range() {
+        assert_eq!(normalize_date("-3é", &today()), Err(DateParse::Invalid));
+        assert_eq!(normalize_date("+é", &today()), Err(DateParse::Invalid));
+        assert_eq!(normalize_date("+9999999999999999y", &today()), Err(DateParse::Invalid));
+        assert_eq!(normalize_date("-9223372036854775807d", &today()), Err(DateParse::Invalid));
+        assert_eq!(normalize_date("+7983y", &today()), Ok("9999-09-07".to_string()));
+        assert_eq!(normalize_date("+7984y", &today()), Err(DateParse::Invalid));
+    }
+
+    #[test]
+    fn normalizes_alternate_formats() {
+        assert_eq!(normalize_date("2016-09-01", &today()), Ok("2016-09-01".to_string()));
+        assert_eq!(normalize_date("2016/09/01", &today()), Ok("2016-09-01".to_string()));
+        assert_eq!(normalize_date("Sep 1 2016", &today()), Ok("2016-09-01".to_string()));
+        assert_eq!(normalize_date("September 1, 2016", &today()), Ok("2016-09-01".to_string()));
+        assert_eq!(normalize_date("1 sept 2016", &today()), Ok("2016-09-01".to_string()));
+        assert_eq!(normalize_date("2016-02-30", &today()), Err(DateParse::Invalid));
+    }
+
+    #[test]
+    fn rejects_ambiguous_dates_with_suggestion() {
+        assert_eq!(normalize_date("9/1/16", &today()),
+                   Err(DateParse::Ambiguous("2016-09-01".to_string())));
+        assert_eq!(normalize_date("13/1/2016", &today()),
+                   Err(DateParse::Ambiguous("2016-01-13".to_string())));
+        assert_eq!(normalize_date("hello", &today()), Err(DateParse::Invalid));
+    }
+
+    #[test]
+    fn shifts_dates_by_months_and_days() {
+        assert_eq!(shift_date("2016-01-31", 1, 0), Some("2016-02-29".to_string()));
+        assert_eq!(shift_date("2016-12-20", 0, 14), Some("2017-01-03".to_string()));
+        assert_eq!(shift_date("2016-02-29", 12, 0), Some("2017-02-28".to_string()));
+        assert_eq!(shift_date("2016-02-30", 1, 0), None);
+    }
+
+    #[test]
+    fn counts_days_between_dates() {
+        assert_eq!(days_between("2016-02-01", "2016-03-01"), Some(29));
+        assert_eq!(days_between("2017-01-01", "2016-12-31"), Some(-1));
+        assert_eq!(days_between("2016-01-01", "soon"), None);
+    }
+}
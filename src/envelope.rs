@@ -0,0 +1,291 @@
+extern crate clap;
+
+use std::fmt;
+use std::path::Path;
+use std::str::FromStr;
+use clap::ArgMatches;
+
+use amount::{ canonical, normalize_amount };
+use base::{ Error, Validation, date_value };
+use config::{ Config, config_path };
+use crypt;
+use date::{ self, format_date };
+use show::month_of;
+use txn::{ Transaction, read_transactions };
+
+pub const SECTION: &str = "envelopes";
+
+/// What happens to an envelope's remainder at the end of a month.
+#[derive(PartialEq, Eq, Debug, Clone, Copy)]
+pub enum CarryOver {
+    /// Every month starts from the limit.
+    Reset,
+    /// Savings and overspending both move into the next month.
+    Rollover,
+    /// Only savings move into the next month.
+    Surplus
+}
+
+impl FromStr for CarryOver {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, ()> {
+        match s {
+            "reset" => Ok(CarryOver::Reset),
+            "rollover" => Ok(CarryOver::Rollover),
+            "surplus" => Ok(CarryOver::Surplus),
+            _ => Err(())
+        }
+    }
+}
+
+impl fmt::Display for CarryOver {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            CarryOver::Reset => write!(f, "reset"),
+            CarryOver::Rollover => write!(f, "rollover"),
+            CarryOver::Surplus => write!(f, "surplus")
+        }
+    }
+}
+
+#[derive(PartialEq, Debug)]
+pub struct Envelope {
+    pub category: String,
+    pub limit: f64,
+    pub carry: CarryOver
+}
+
+impl Envelope {
+    /// Reads a config line such as `groceries = 600 rollover`.
+    fn from_config(category: &str, value: &str) -> Option<Self> {
+        let mut words = value.split_whitespace();
+        let limit = words.next().and_then(normalize_amount).and_then(|l| f64::from_str(&l).ok())?;
+        let carry = match words.next() {
+            Some(rule) => CarryOver::from_str(rule).ok()?,
+            None => CarryOver::Reset
+        };
+        Some(Envelope { category: category.to_string(), limit, carry })
+    }
+
+    fn carried(&self, remaining: f64) -> f64 {
+        match self.carry {
+            CarryOver::Reset => 0.0,
+            CarryOver::Rollover => remaining,
+            CarryOver::Surplus => remaining.max(0.0)
+        }
+    }
+}
+
+/// One envelope in one month, built like a `Delta`: what was available
+/// against what was spent.
+#[derive(PartialEq, Debug)]
+pub struct EnvelopeMonth<'a> {
+    envelope: &'a Envelope,
+    carried: f64,
+    spent: f64
+}
+
+impl<'a> EnvelopeMonth<'a> {
+    fn remaining(&self) -> f64 {
+        self.envelope.limit + self.carried - self.spent
+    }
+}
+
+impl<'a> fmt::Display for EnvelopeMonth<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}: {} + {} carried -> spent {} | remaining {}", self.envelope.category,
+                                                                   canonical(self.envelope.limit),
+                                                                   canonical(self.carried),
+                                                                   canonical(self.spent),
+                                                                   canonical(self.remaining()))?;
+        if self.remaining() < 0.0 {
+            write!(f, " (over)")?;
+        }
+        Ok(())
+    }
+}
+
+pub fn envelopes(config: &Config) -> Vec<Envelope> {
+    config.section(SECTION)
+          .iter()
+          .filter_map(|(category, value)| {
+              let envelope = Envelope::from_config(category, value);
+              if envelope.is_none() {
+                  println!("Ignoring envelope {} = {}; expected a limit and reset, rollover or surplus",
+                           category, value);
+              }
+              envelope
+          })
+          .collect()
+}
+
+fn next_month(month: &str) -> String {
+    let year = month.get(..4).and_then(|y| y.parse::<i32>().ok()).unwrap_or(0);
+    let number = month.get(5..7).and_then(|m| m.parse::<i32>().ok()).unwrap_or(1);
+    if number == 12 {
+        format!("{:04}-01", year + 1)
+    } else {
+        format!("{:04}-{:02}", year, number + 1)
+    }
+}
+
+/// Transactions edited by hand into something unreadable are left out
+/// rather than counted in some month.
+fn usable(transaction: &Transaction) -> bool {
+    transaction.validate() == Validation::Valid
+}
+
+/// Spending is the negated sum of the category's transactions in `month`,
+/// so refunds reduce it.
+fn spent(transactions: &[Transaction], category: &str, month: &str) -> f64 {
+    -transactions.iter()
+                 .filter(|t| usable(t) && t.category() == category && month_of(&t.date_string) == month)
+                 .map(|t| t.amount())
+                 .sum::<f64>()
+}
+
+/// Walks the envelope forward from the first month with spending in its
+/// category, carrying remainders by its rule, up to `month`.
+pub fn envelope_month<'a>(envelope: &'a Envelope, transactions: &[Transaction], month: &str) -> EnvelopeMonth<'a> {
+    let first = transactions.iter()
+                            .filter(|t| usable(t) && t.category() == envelope.category)
+                            .map(|t| month_of(&t.date_string).to_string())
+                            .min()
+                            .unwrap_or_else(|| month.to_string());
+    let mut current = first;
+    let mut carried = 0.0;
+    while current.as_str() < month {
+        let past = EnvelopeMonth { envelope,
+                                   carried,
+                                   spent: spent(transactions, &envelope.category, &current) };
+        carried = envelope.carried(past.remaining());
+        current = next_month(&current);
+    }
+    EnvelopeMonth { envelope, carried, spent: spent(transactions, &envelope.category, month) }
+}
+
+pub fn run_envelope(data_path: &Path, matches: &ArgMatches) -> Result<bool, Error> {
+    let submatches = matches.subcommand_matches("envelope").ok_or(Error::InputError)?;
+    let path = config_path(matches, data_path);
+    let mut config = Config::read(&path)?;
+
+    if let Some(set_matches) = submatches.subcommand_matches("set") {
+        let category = set_matches.value_of("category").ok_or(Error::InputError)?;
+        let limit = set_matches.value_of("limit")
+                               .and_then(normalize_amount)
+                               .ok_or_else(|| {
+                                   println!("Invalid Limit; must be a number like 600");
+                                   Error::InputError
+                               })?;
+        let carry = set_matches.value_of("carry").unwrap_or("reset");
+        config.set(SECTION, category, &format!("{} {}", limit, carry));
+        return config.write(&path)
+    }
+
+    let month = date_value(submatches, "month")?.unwrap_or_else(|| format_date(&date::today()));
+    let month = month_of(&month);
+    let transactions = read_transactions(data_path).map_err(crypt::read_error)?;
+    let envelopes = envelopes(&config);
+    if envelopes.is_empty() {
+        println!("No envelopes in {}; add one with envelope set <category> <limit>", path.display());
+        return Ok(false)
+    }
+
+    println!("Envelopes for {}", month);
+    for envelope in &envelopes {
+        println!("{}", envelope_month(envelope, &transactions, month));
+    }
+    let mut unbudgeted = transactions.iter()
+                                     .map(|t| t.category())
+                                     .filter(|c| envelopes.iter().all(|e| e.category != *c))
+                                     .collect::<Vec<&str>>();
+    unbudgeted.sort();
+    unbudgeted.dedup();
+    for category in unbudgeted {
+        let amount = spent(&transactions, category, month);
+        if amount != 0.0 {
+            println!("{}: spent {} without an envelope", category, canonical(amount));
+        }
+    }
+    Ok(true)
+}
+
+#[cfg(test)]
+mod test {
+    use config::Config;
+    use txn::Transaction;
+    use super::{ CarryOver, Envelope, envelopes, envelope_month, next_month };
+
+    fn transactions() -> Vec<Transaction> {
+        vec![Transaction::new("2016-01-05", "-450.00").with_category("groceries"),
+             Transaction::new("2016-01-20", "-250.00").with_category("dining"),
+             Transaction::new("2016-02-03", "-700.00").with_category("groceries"),
+             Transaction::new("2016-02-10", "25.00").with_category("groceries"),
+             Transaction::new("2016-03-02", "-320.50").with_category("groceries"),
+             Transaction::new("2016-03-04", "-90.00").with_category("dining"),
+             Transaction::new("2016-03-05", "-12.00")
+            ]
+    }
+
+    fn envelope(carry: CarryOver) -> Envelope {
+        Envelope { category: "groceries".to_string(), limit: 600.0, carry }
+    }
+
+    #[test]
+    fn reads_envelopes_from_config() {
+        let config = Config::parse("[envelopes]\ngroceries = 600 rollover\ndining = $200\nrent = lots\n");
+
+        assert_eq!(envelopes(&config),
+                   vec![Envelope { category: "groceries".to_string(), limit: 600.0, carry: CarryOver::Rollover },
+                        Envelope { category: "dining".to_string(), limit: 200.0, carry: CarryOver::Reset }]);
+    }
+
+    #[test]
+    fn reset_envelope_starts_each_month_at_limit() {
+        let envelope = envelope(CarryOver::Reset);
+        let march = envelope_month(&envelope, &transactions(), "2016-03");
+
+        assert_eq!(format!("{}", march),
+                   "groceries: 600.00 + 0.00 carried -> spent 320.50 | remaining 279.50");
+    }
+
+    #[test]
+    fn rollover_envelope_carries_savings_and_overspending() {
+        let envelope = envelope(CarryOver::Rollover);
+
+        // January leaves 150, February spends 675 of 750
+        assert_eq!(format!("{}", envelope_month(&envelope, &transactions(), "2016-02")),
+                   "groceries: 600.00 + 150.00 carried -> spent 675.00 | remaining 75.00");
+        assert_eq!(format!("{}", envelope_month(&envelope, &transactions(), "2016-03")),
+                   "groceries: 600.00 + 75.00 carried -> spent 320.50 | remaining 354.50");
+    }
+
+    #[test]
+    fn surplus_envelope_drops_overspending() {
+        let envelope = Envelope { category: "groceries".to_string(), limit: 500.0, carry: CarryOver::Surplus };
+
+        // January leaves 50, February overspends by 125
+        assert_eq!(format!("{}", envelope_month(&envelope, &transactions(), "2016-02")),
+                   "groceries: 500.00 + 50.00 carried -> spent 675.00 | remaining -125.00 (over)");
+        assert_eq!(format!("{}", envelope_month(&envelope, &transactions(), "2016-03")),
+                   "groceries: 500.00 + 0.00 carried -> spent 320.50 | remaining 179.50");
+    }
+
+    #[test]
+    fn steps_months_across_years() {
+        assert_eq!(next_month("2016-11"), "2016-12");
+        assert_eq!(next_month("2016-12"), "2017-01");
+        assert_eq!(next_month("1"), "0000-02");
+    }
+
+    #[test]
+    fn skips_unreadable_transactions() {
+        let mut edited = transactions();
+        edited.push(Transaction::new("1", "-5.00").with_category("groceries"));
+        edited.push(Transaction::new("2016-03-02", "lots").with_category("groceries"));
+        let envelope = envelope(CarryOver::Rollover);
+
+        assert_eq!(envelope_month(&envelope, &edited, "2016-03"), envelope_month(&envelope, &transactions(), "2016-03"));
+    }
+}
@@ -0,0 +1,54 @@
+extern crate clap;
+
+use std::fs::File;
+use std::io::Write;
+use std::path::Path;
+use std::str::FromStr;
+use clap::ArgMatches;
+
+use base::{ Entry, Error };
+use crypt;
+use date::{ self, format_date };
+use json::{ Account, Document, account_name, to_json };
+use ledger::{ LedgerFormat, to_ledger };
+use show::read_file;
+use txn::read_transactions;
+
+/// The ledger as a JSON document holding the one account `name`.
+pub fn document(data_path: &Path, name: String, entries: Vec<Entry>) -> Result<Document, Error> {
+    let mut account = Account::new(name, entries);
+    account.transactions = read_transactions(data_path).map_err(crypt::read_error)?;
+    let mut document = Document::new(vec![account]);
+    document.metadata.insert("exported".to_string(), format_date(&date::today()));
+    Ok(document)
+}
+
+pub fn run_export(data_path: &Path, matches: &ArgMatches) -> Result<bool, Error> {
+    let submatches = matches.subcommand_matches("export").ok_or(Error::InputError)?;
+    let entries = read_file(data_path).map_err(crypt::read_error)?;
+    let exported = if submatches.is_present("json") {
+        let name = submatches.value_of("account")
+                             .map(|name| name.to_string())
+                             .unwrap_or_else(|| account_name(data_path));
+        to_json(&document(data_path, name, entries)?)
+    } else {
+        let format = submatches.value_of("format")
+                               .ok_or(Error::InputError)
+                               .and_then(|f| LedgerFormat::from_str(f).map_err(|_| Error::InputError))?;
+        to_ledger(&entries,
+                  format,
+                  submatches.value_of("account").unwrap_or("Assets:Checking"),
+                  submatches.value_of("commodity").unwrap_or("USD"),
+                  submatches.is_present("open"))
+    };
+
+    match submatches.value_of("output") {
+        Some(path) => File::create(path).and_then(|mut f| f.write_all(exported.as_bytes()))
+                                        .map(|_| true)
+                                        .map_err(|_| Error::WriteError),
+        None => {
+            print!("{}", exported);
+            Ok(true)
+        }
+    }
+}
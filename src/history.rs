@@ -0,0 +1,231 @@
+extern crate clap;
+
+use std::fmt;
+use std::path::Path;
+use std::process::Command;
+use clap::ArgMatches;
+
+use base::{ Entry, Error, date_value };
+use config::{ Config, default_config_path };
+use journal::{ Change, Record, journal_path };
+use storage::{ self, Line };
+
+/// Versioning is opted into per ledger, in its own config file:
+///
+/// ```text
+/// [git]
+/// commit = yes
+/// ```
+pub fn enabled(data_path: &Path) -> bool {
+    Config::read(&default_config_path(data_path))
+           .map(|config| config.section("git")
+                               .iter()
+                               .any(|(k, v)| k == "commit" && ["yes", "true", "on"].contains(&v.as_str())))
+           .unwrap_or(false)
+}
+
+fn describe_line(line: &Line) -> String {
+    match *line {
+        Line::Entry(ref entry) => format!("{} {}", entry.date_string, entry.amount_string),
+        Line::Transaction(ref transaction) => format!("txn {} {}", transaction.date_string, transaction.amount_string)
+    }
+}
+
+fn amount(line: &Line) -> &str {
+    match *line {
+        Line::Entry(ref entry) => &entry.amount_string,
+        Line::Transaction(ref transaction) => &transaction.amount_string
+    }
+}
+
+/// A change in a few words, such as `add 2016-03-05 1250.00`.
+pub fn describe(change: &Change) -> String {
+    match (&change.before, &change.after) {
+        (Some(before), Some(after)) if amount(before) == amount(after) => format!("edit {}", describe_line(after)),
+        (Some(before), Some(after)) => format!("edit {} to {}", describe_line(before), amount(after)),
+        (Some(before), None) => format!("remove {}", describe_line(before)),
+        (None, Some(after)) => format!("add {}", describe_line(after)),
+        (None, None) => String::new()
+    }
+}
+
+/// A subject naming the change when there is only one, then a line per
+/// change and the journal records the commit covers.
+pub fn commit_message(records: &[Record]) -> String {
+    let changes = records.iter().flat_map(|r| r.changes.iter()).collect::<Vec<&Change>>();
+    let mut message = match changes.len() {
+        1 => describe(changes[0]),
+        n => format!("{} {} changes", records[0].operation, n)
+    };
+    message.push('\n');
+    if changes.len() > 1 {
+        message.push('\n');
+        for change in &changes {
+            message.push_str(&format!("{}\n", describe(change)));
+        }
+    }
+    let ids = records.iter()
+                     .map(|r| format!("#{} {}", r.id, r.operation))
+                     .collect::<Vec<String>>();
+    message.push_str(&format!("\njournal {}\n", ids.join(", ")));
+    message
+}
+
+fn git(data_path: &Path, args: &[&str]) -> Result<String, String> {
+    git_bytes(data_path, args).map(|out| String::from_utf8_lossy(&out).into_owned())
+}
+
+fn git_bytes(data_path: &Path, args: &[&str]) -> Result<Vec<u8>, String> {
+    let dir = data_path.parent().filter(|d| !d.as_os_str().is_empty()).unwrap_or_else(|| Path::new("."));
+    let output = Command::new("git").arg("-C")
+                                    .arg(dir)
+                                    .args(args)
+                                    .output()
+                                    .map_err(|e| format!("Could not run git: {}", e))?;
+    if output.status.success() {
+        Ok(output.stdout)
+    } else {
+        Err(String::from_utf8_lossy(&output.stderr).trim().to_string())
+    }
+}
+
+fn file_name(path: &Path) -> String {
+    format!("./{}", path.file_name().map_or(String::new(), |n| n.to_string_lossy().into_owned()))
+}
+
+/// Commits the ledger and its journal after `records` were written, when
+/// versioning is enabled. The ledger has already been changed by then, so a
+/// failed commit is reported rather than treated as an error.
+pub fn commit(data_path: &Path, records: &[Record]) {
+    if records.is_empty() || !enabled(data_path) {
+        return
+    }
+    let files = [file_name(data_path), file_name(&journal_path(data_path))];
+    let message = commit_message(records);
+    let committed = git(data_path, &["add", "--", &files[0], &files[1]])
+        .and_then(|_| git(data_path, &["commit", "-q", "-m", &message, "--", &files[0], &files[1]]));
+    if let Err(e) = committed {
+        println!("Changed the ledger but could not commit it: {}", e);
+    }
+}
+
+/// The entry on a date as of one commit; `None` once it has been removed.
+#[derive(PartialEq, Eq, Debug)]
+pub struct Version {
+    pub time: String,
+    pub subject: String,
+    pub entry: Option<Entry>
+}
+
+impl fmt::Display for Version {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let value = match self.entry {
+            Some(ref entry) => format!("{}{}", entry.amount_string, if entry.reconciled { "*" } else { "" }),
+            None => "removed".to_string()
+        };
+        write!(f, "{}  {:>12}  {}", self.time, value, self.subject)
+    }
+}
+
+/// Each commit that changed the entry on `date`, oldest first.
+pub fn history(data_path: &Path, date: &str) -> Result<Vec<Version>, String> {
+    let file = file_name(data_path);
+    let log = git(data_path, &["log", "--reverse", "--date=format:%Y-%m-%d %H:%M",
+                               "--format=%H%x09%cd%x09%s", "--", &file])?;
+    let mut versions: Vec<Version> = Vec::new();
+    for line in log.lines() {
+        let fields = line.splitn(3, '\t').collect::<Vec<&str>>();
+        if fields.len() < 3 {
+            continue
+        }
+        let entries = git_bytes(data_path, &["show", &format!("{}:{}", fields[0], file)])
+                          .and_then(|bytes| storage::entries_in(data_path, bytes).map_err(|e| e.to_string()))?;
+        let entry = entries.into_iter().rfind(|e| e.date_string == date);
+        let previous = versions.last().map(|v| &v.entry);
+        if previous != Some(&entry) && (entry.is_some() || previous.is_some()) {
+            versions.push(Version { time: fields[1].to_string(), subject: fields[2].to_string(), entry });
+        }
+    }
+    Ok(versions)
+}
+
+pub fn run_history(data_path: &Path, matches: &ArgMatches) -> Result<bool, Error> {
+    let submatches = matches.subcommand_matches("history").ok_or(Error::InputError)?;
+    let date = date_value(submatches, "date")?.ok_or(Error::InputError)?;
+    match history(data_path, &date) {
+        Ok(ref versions) if versions.is_empty() => {
+            println!("No committed entry on {}", date);
+            Ok(false)
+        },
+        Ok(versions) => {
+            for version in versions {
+                println!("{}", version);
+            }
+            Ok(true)
+        },
+        Err(e) => {
+            println!("Could not read the history of {}: {}", data_path.display(), e);
+            Err(Error::ReadError)
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::env;
+    use std::fs;
+    use std::path::{ Path, PathBuf };
+    use add::{ write_entries, write_to_file };
+    use base::Entry;
+    use journal::recorded;
+    use super::{ git, history };
+
+    fn repo(name: &str) -> PathBuf {
+        let dir = env::temp_dir().join(format!("budget_history_{}_{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir(&dir).unwrap();
+        let path = dir.join("ledger");
+        git(&path, &["init", "-q"]).unwrap();
+        git(&path, &["config", "user.name", "Budget Test"]).unwrap();
+        git(&path, &["config", "user.email", "budget@example.com"]).unwrap();
+        git(&path, &["config", "commit.gpgsign", "false"]).unwrap();
+        fs::write(dir.join("ledger.conf"), "[git]\ncommit = yes\n").unwrap();
+        path
+    }
+
+    fn subjects(path: &Path) -> Vec<String> {
+        git(path, &["log", "--format=%s"]).unwrap().lines().map(|l| l.to_string()).collect()
+    }
+
+    #[test]
+    fn commits_each_change_and_traces_an_entry() {
+        let path = repo("trace");
+        recorded(&path, "add", || write_to_file(&Entry::new("2016-03-05", "1250.00"), &path)).unwrap();
+        recorded(&path, "add", || write_to_file(&Entry::new("2016-04-01", "1300.00"), &path)).unwrap();
+        recorded(&path, "reconcile", || write_entries(&[Entry::new("2016-03-05", "1200.00").with_reconciled(),
+                                                        Entry::new("2016-04-01", "1300.00")], &path)).unwrap();
+        recorded(&path, "tui", || write_entries(&[Entry::new("2016-04-01", "1300.00")], &path)).unwrap();
+
+        assert_eq!(subjects(&path), vec!["remove 2016-03-05 1200.00",
+                                         "edit 2016-03-05 1250.00 to 1200.00",
+                                         "add 2016-04-01 1300.00",
+                                         "add 2016-03-05 1250.00"]);
+        let versions = history(&path, "2016-03-05").unwrap();
+        assert_eq!(versions.iter().map(|v| v.entry.clone()).collect::<Vec<Option<Entry>>>(),
+                   vec![Some(Entry::new("2016-03-05", "1250.00")),
+                        Some(Entry::new("2016-03-05", "1200.00").with_reconciled()),
+                        None]);
+        assert_eq!(versions[1].subject, "edit 2016-03-05 1250.00 to 1200.00");
+        fs::remove_dir_all(path.parent().unwrap()).unwrap();
+    }
+
+    #[test]
+    fn leaves_the_repository_alone_unless_enabled() {
+        let path = repo("disabled");
+        fs::write(path.with_extension("conf"), "").unwrap();
+        recorded(&path, "add", || write_to_file(&Entry::new("2016-03-05", "1250.00"), &path)).unwrap();
+
+        assert!(git(&path, &["log"]).is_err());
+        fs::remove_dir_all(path.parent().unwrap()).unwrap();
+    }
+}
@@ -0,0 +1,340 @@
+extern crate clap;
+
+use std::fmt;
+use std::fs::File;
+use std::io::{ BufReader, Read };
+use std::path::Path;
+use clap::ArgMatches;
+
+use add::{ write_to_file, write_entries, invalid_message };
+use base::{ Entry, Validation, Error };
+use csv::{ Column, CsvMapping, entries_from_csv };
+use crypt;
+use date;
+use journal;
+use json::from_json;
+use ledger::entries_from_ledger;
+use ofx::balances_from_ofx;
+use show::{ month_of, read_entries };
+use storage::Line;
+use txn::{ Transaction, read_transactions };
+
+#[derive(PartialEq, Eq, Debug)]
+enum Change<'a> {
+    Add(&'a Entry),
+    Duplicate(&'a Entry, &'a Entry),
+    /// An imported entry that differs from the one recorded on its date, in
+    /// a format that carries every field.
+    Replace(&'a Entry, &'a Entry)
+}
+
+impl<'a> fmt::Display for Change<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Change::Add(entry) =>
+                write!(f, "add  {} {}", entry.date_string, entry.amount_string),
+            Change::Duplicate(entry, existing) if entry.amount() == existing.amount() =>
+                write!(f, "skip {} {} (already recorded)", entry.date_string, entry.amount_string),
+            Change::Duplicate(entry, existing) =>
+                write!(f, "skip {} {} (ledger has {})", entry.date_string,
+                                                         entry.amount_string,
+                                                         existing.amount_string),
+            Change::Replace(entry, existing) =>
+                write!(f, "replace {} {} (differs in {})", entry.date_string,
+                                                            entry.amount_string,
+                                                            entry.differences(existing).join(", "))
+        }
+    }
+}
+
+pub fn run_import(data_path: &Path, matches: &ArgMatches) -> Result<bool, Error> {
+    let submatches = matches.subcommand_matches("import").ok_or(Error::InputError)?;
+    let (imported, transactions) = if submatches.is_present("json") {
+        read_json(submatches)?
+    } else if submatches.is_present("ofx") {
+        (read_ofx(submatches)?, Vec::new())
+    } else if submatches.is_present("ledger") {
+        (read_ledger(submatches)?, Vec::new())
+    } else {
+        (read_csv(submatches)?, Vec::new())
+    };
+    let imported = if submatches.is_present("month_end") { month_end(imported) } else { imported };
+
+    import(data_path, &imported, &transactions, submatches.is_present("json"), submatches.is_present("dry_run"))
+}
+
+/// Previews the import and then, unless `dry_run`, writes it. With
+/// `replace`, an entry that differs from the one recorded on its date
+/// replaces it; otherwise the recorded one is kept.
+fn import(data_path: &Path,
+          imported: &[Entry],
+          transactions: &[Transaction],
+          replace: bool,
+          dry_run: bool) -> Result<bool, Error> {
+    let existing = read_entries(data_path)?;
+    let changes = plan_import(&existing, imported, replace);
+    for change in &changes {
+        println!("{}", change);
+    }
+
+    let additions = changes.iter()
+                           .filter_map(|change| match *change {
+                               Change::Add(entry) => Some(entry),
+                               _ => None
+                           })
+                           .collect::<Vec<&Entry>>();
+    let replacements = changes.iter()
+                              .filter_map(|change| match *change {
+                                  Change::Replace(entry, existing) => Some((existing, entry)),
+                                  _ => None
+                              })
+                              .collect::<Vec<(&Entry, &Entry)>>();
+    for entry in additions.iter().chain(replacements.iter().map(|r| &r.1)) {
+        match entry.validate() {
+            Validation::Valid => {},
+            invalid => {
+                println!("{}", invalid_message(entry, &invalid));
+                return Err(Error::InputError)
+            }
+        }
+    }
+
+    let recorded = read_transactions(data_path).map_err(crypt::read_error)?;
+    let new_transactions = transactions.iter()
+                                       .filter(|t| !recorded.contains(t))
+                                       .collect::<Vec<&Transaction>>();
+    for transaction in &new_transactions {
+        print!("add  {}", transaction);
+    }
+
+    println!("{} new, {} replaced, {} already in ledger", additions.len(),
+                                                          replacements.len(),
+                                                          changes.len() - additions.len() - replacements.len());
+    if !transactions.is_empty() {
+        println!("{} new transactions, {} already in ledger", new_transactions.len(),
+                                                              transactions.len() - new_transactions.len());
+    }
+    if dry_run {
+        return Ok(true)
+    }
+    if replacements.is_empty() && appendable(&existing, &additions) {
+        let lines = additions.iter()
+                             .map(|entry| Line::from((*entry).clone()))
+                             .chain(new_transactions.iter().map(|transaction| Line::from((*transaction).clone())))
+                             .collect::<Vec<Line>>();
+        return journal::appended(data_path, "import", &lines)
+    }
+    journal::recorded(data_path, "import", || {
+        let mut merged = existing.iter()
+                                 .map(|entry| replacements.iter()
+                                                          .find(|r| std::ptr::eq(r.0, entry))
+                                                          .map_or(entry, |r| r.1))
+                                 .chain(additions.iter().cloned())
+                                 .cloned()
+                                 .collect::<Vec<Entry>>();
+        merged.sort_by(|a, b| a.date_string.cmp(&b.date_string));
+        write_entries(&merged, data_path)?;
+        new_transactions.iter()
+                        .map(|transaction| write_to_file(*transaction, data_path))
+                        .collect::<Result<Vec<bool>, Error>>()
+                        .map(|_| true)
+    })
+}
+
+fn read_csv(submatches: &ArgMatches) -> Result<Vec<Entry>, Error> {
+    let path = submatches.value_of("csv").ok_or(Error::InputError)?;
+    let mapping = CsvMapping {
+        date_column: Column::parse(submatches.value_of("date_column").unwrap_or("Date")),
+        balance_column: Column::parse(submatches.value_of("balance_column").unwrap_or("Balance")),
+        date_format: submatches.value_of("date_format").map(|f| f.to_string()),
+        delimiter: submatches.value_of("delimiter")
+                             .map(|d| if d == "\\t" { "\t" } else { d })
+                             .and_then(|d| d.chars().next())
+                             .unwrap_or(',')
+    };
+    let f = File::open(path).map_err(|_| Error::ReadError)?;
+    let (entries, skipped) = entries_from_csv(BufReader::new(f), &mapping, &date::today());
+    for problem in &skipped {
+        println!("skipped {}", problem);
+    }
+    Ok(entries)
+}
+
+fn read_ofx(submatches: &ArgMatches) -> Result<Vec<Entry>, Error> {
+    let path = submatches.value_of("ofx").ok_or(Error::InputError)?;
+    let mut contents = Vec::new();
+    File::open(path).and_then(|mut f| f.read_to_end(&mut contents))
+                    .map_err(|_| Error::ReadError)?;
+    let entries = balances_from_ofx(&String::from_utf8_lossy(&contents),
+                                    submatches.is_present("available"));
+    if entries.is_empty() {
+        println!("No LEDGERBAL balances found in {}", path);
+    }
+    Ok(entries)
+}
+
+fn read_ledger(submatches: &ArgMatches) -> Result<Vec<Entry>, Error> {
+    let path = submatches.value_of("ledger").ok_or(Error::InputError)?;
+    let mut contents = String::new();
+    File::open(path).and_then(|mut f| f.read_to_string(&mut contents))
+                    .map_err(|_| Error::ReadError)?;
+    let mut entries = entries_from_ledger(&contents, submatches.value_of("account"));
+    entries.sort_by(|a, b| a.date_string.cmp(&b.date_string));
+    Ok(entries)
+}
+
+fn read_json(submatches: &ArgMatches) -> Result<(Vec<Entry>, Vec<Transaction>), Error> {
+    let path = submatches.value_of("json").ok_or(Error::InputError)?;
+    let mut contents = String::new();
+    File::open(path).and_then(|mut f| f.read_to_string(&mut contents))
+                    .map_err(|_| Error::ReadError)?;
+    let document = from_json(&contents).map_err(|e| {
+        println!("Invalid JSON ledger {}: {}", path, e);
+        Error::InputError
+    })?;
+    let account = document.account(submatches.value_of("account")).map_err(|e| {
+        println!("{}", e);
+        Error::InputError
+    })?;
+    let mut entries = account.entries.clone();
+    entries.sort_by(|a, b| a.date_string.cmp(&b.date_string));
+    Ok((entries, account.transactions.clone()))
+}
+
+/// Keeps the last entry of each calendar month.
+fn month_end(entries: Vec<Entry>) -> Vec<Entry> {
+    let mut kept: Vec<Entry> = Vec::new();
+    for entry in entries {
+        if kept.last().is_some_and(|last| month_of(&last.date_string) == month_of(&entry.date_string)) {
+            kept.pop();
+        }
+        kept.push(entry);
+    }
+    kept
+}
+
+/// Pairs each imported entry with the ledger entry sharing its date, if any;
+/// only the last imported entry for a date is kept.
+fn plan_import<'a>(existing: &'a [Entry], imported: &'a [Entry], replace: bool) -> Vec<Change<'a>> {
+    imported.iter()
+            .enumerate()
+            .filter(|&(i, entry)| {
+                imported.get(i + 1).is_none_or(|next| next.date_string != entry.date_string)
+            })
+            .map(|(_, entry)| {
+                match existing.iter().find(|e| e.date_string == entry.date_string) {
+                    Some(recorded) if replace && recorded != entry => Change::Replace(entry, recorded),
+                    Some(recorded) => Change::Duplicate(entry, recorded),
+                    None => Change::Add(entry)
+                }
+            })
+            .collect()
+}
+
+// Appending keeps the ledger in date order only when every new entry is
+// later than the last recorded one; otherwise the merged ledger is rewritten.
+fn appendable(existing: &[Entry], additions: &[&Entry]) -> bool {
+    let last_recorded = existing.last().map(|e| e.date_string.as_str()).unwrap_or("");
+    additions.iter().all(|entry| entry.date_string.as_str() > last_recorded)
+}
+
+#[cfg(test)]
+mod test {
+    use std::env;
+    use std::fs;
+    use std::path::{ Path, PathBuf };
+    use add::{ write_entries, write_to_file };
+    use base::Entry;
+    use export::document;
+    use journal::journal_path;
+    use json::{ from_json, to_json };
+    use show::read_file;
+    use txn::{ Transaction, read_transactions };
+    use super::{ Change, import, month_end, plan_import };
+
+    fn ledger(name: &str, entries: &[Entry]) -> PathBuf {
+        let path = env::temp_dir().join(format!("budget_import_{}_{}", name, std::process::id()));
+        let _ = fs::remove_file(&path);
+        write_entries(entries, &path).unwrap();
+        path
+    }
+
+    fn import_json(json: &str, path: &Path) {
+        let document = from_json(json).unwrap();
+        let account = document.account(None).unwrap();
+        import(path, &account.entries, &account.transactions, true, false).unwrap();
+    }
+
+    fn remove(path: &Path) {
+        fs::remove_file(path).unwrap();
+        let _ = fs::remove_file(journal_path(path));
+    }
+
+    #[test]
+    fn json_round_trips_through_export_and_import() {
+        let entries = vec![Entry::new("2016-01-01", "1000.00").with_reconciled(),
+                           Entry::new("2016-02-01", "1200.00").with_note("bonus | raise").with_tags(&["work"]),
+                           Entry::new("2016-03-01", "900.00")];
+        let source = ledger("source", &entries);
+        write_to_file(&Transaction::new("2016-01-20", "-40.00").with_payee("Grocer"), &source).unwrap();
+        let json = to_json(&document(&source, "budget".to_string(), read_file(&source).unwrap()).unwrap());
+
+        let empty = ledger("empty", &[]);
+        import_json(&json, &empty);
+        assert_eq!(read_file(&empty).unwrap(), entries);
+        assert_eq!(read_transactions(&empty).unwrap(), read_transactions(&source).unwrap());
+
+        let populated = ledger("populated", &[Entry::new("2016-01-01", "1000.00"),
+                                              Entry::new("2016-01-15", "1100.00"),
+                                              Entry::new("2016-02-01", "1250.00")]);
+        import_json(&json, &populated);
+        assert_eq!(read_file(&populated).unwrap(), vec![entries[0].clone(),
+                                                        Entry::new("2016-01-15", "1100.00"),
+                                                        entries[1].clone(),
+                                                        entries[2].clone()]);
+        for path in &[source, empty, populated] {
+            remove(path);
+        }
+    }
+
+    #[test]
+    fn keeps_last_entry_of_each_month() {
+        let entries = vec![Entry::new("2016-01-15", "1500.00"),
+                           Entry::new("2016-01-31", "2500.00"),
+                           Entry::new("2016-02-15", "2300.00"),
+                           Entry::new("2016-02-29", "1250.00"),
+                           Entry::new("2016-04-01", "1100.00")
+                          ];
+
+        assert_eq!(month_end(entries), vec![Entry::new("2016-01-31", "2500.00"),
+                                            Entry::new("2016-02-29", "1250.00"),
+                                            Entry::new("2016-04-01", "1100.00")
+                                           ]);
+    }
+
+    #[test]
+    fn dedupes_against_ledger_dates() {
+        let existing = vec![Entry::new("2016-01-31", "2500.00"),
+                            Entry::new("2016-02-29", "1200.00")
+                           ];
+        let imported = vec![Entry::new("2016-01-31", "2500.00"),
+                            Entry::new("2016-02-29", "1300.00"),
+                            Entry::new("2016-02-29", "1250.00"),
+                            Entry::new("2016-03-31", "1400.00")
+                           ];
+
+        let changes = plan_import(&existing, &imported, false);
+        let replacing = plan_import(&existing, &imported, true);
+
+        assert_eq!(changes, vec![Change::Duplicate(&imported[0], &existing[0]),
+                                 Change::Duplicate(&imported[2], &existing[1]),
+                                 Change::Add(&imported[3])
+                                ]);
+        assert_eq!(format!("{}", changes[0]), "skip 2016-01-31 2500.00 (already recorded)");
+        assert_eq!(format!("{}", changes[1]), "skip 2016-02-29 1250.00 (ledger has 1200.00)");
+        assert_eq!(format!("{}", changes[2]), "add  2016-03-31 1400.00");
+        assert_eq!(replacing[1], Change::Replace(&imported[2], &existing[1]));
+        assert_eq!(format!("{}", replacing[1]), "replace 2016-02-29 1250.00 (differs in amount)");
+        assert_eq!(replacing[0], changes[0]);
+    }
+}
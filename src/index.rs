@@ -0,0 +1,169 @@
+use std::fs::{ self, File };
+use std::io::{ self, BufRead, BufReader, ErrorKind, Seek, SeekFrom };
+use std::path::{ Path, PathBuf };
+use serde_json;
+
+use txn::is_transaction_line;
+use watch::{ Stamp, stamp };
+
+/// What one pass over a plain text ledger found, kept beside it in
+/// `<ledger>.index` so that later reads can seek instead of scanning. It is
+/// only trusted while the ledger's stamp is the one it was built from.
+#[derive(PartialEq, Eq, Debug, Serialize, Deserialize)]
+pub struct Index {
+    pub stamp: Stamp,
+    /// Whether the entries are in date order, so that a date can be found
+    /// by binary search.
+    pub sorted: bool,
+    pub transactions: bool,
+    /// The date of the last entry, to keep the index current across appends.
+    pub last: Option<String>
+}
+
+pub fn index_path(data_path: &Path) -> PathBuf {
+    let mut path = data_path.as_os_str().to_os_string();
+    path.push(".index");
+    PathBuf::from(path)
+}
+
+/// The date of an entry line, without parsing the rest of it.
+pub fn entry_date(line: &str) -> &str {
+    line.split('|').next().unwrap_or("").trim()
+}
+
+impl Index {
+    /// Reads every line of the ledger once.
+    pub fn scan(path: &Path) -> io::Result<Index> {
+        let stamp = stamp(path).ok_or_else(|| io::Error::new(ErrorKind::NotFound,
+                                                             format!("{} does not exist", path.display())))?;
+        let mut index = Index { stamp, sorted: true, transactions: false, last: None };
+        for line in BufReader::new(File::open(path)?).lines() {
+            index.add(&line?);
+        }
+        Ok(index)
+    }
+
+    /// Accounts for a line following those already indexed.
+    pub fn add(&mut self, line: &str) {
+        if line.trim().is_empty() {
+            return
+        }
+        if is_transaction_line(line) {
+            self.transactions = true;
+            return
+        }
+        let date = entry_date(line);
+        if self.last.as_ref().is_some_and(|last| date < last.as_str()) {
+            self.sorted = false;
+        }
+        self.last = Some(date.to_string());
+    }
+
+    /// The saved index, if it still describes the ledger.
+    pub fn saved(path: &Path) -> Option<Index> {
+        let index: Index = serde_json::from_str(&fs::read_to_string(index_path(path)).ok()?).ok()?;
+        if Some(index.stamp) == stamp(path) { Some(index) } else { None }
+    }
+
+    /// The saved index, or a fresh scan saved for next time.
+    pub fn current(path: &Path) -> io::Result<Index> {
+        match Index::saved(path) {
+            Some(index) => Ok(index),
+            None => Index::scan(path).inspect(|index| index.save(path))
+        }
+    }
+
+    /// The index only saves rereading the ledger, so failing to write it
+    /// is not an error.
+    pub fn save(&self, path: &Path) {
+        if let Ok(json) = serde_json::to_string(self) {
+            let _ = fs::write(index_path(path), json);
+        }
+    }
+}
+
+/// The first entry line starting at or after `offset`, with where it
+/// starts.
+fn entry_after<R: BufRead + Seek>(reader: &mut R, offset: u64) -> io::Result<Option<(u64, String)>> {
+    let mut buffer = Vec::new();
+    // back up a byte so that a line starting at `offset` is not skipped
+    let mut position = offset.saturating_sub(1);
+    reader.seek(SeekFrom::Start(position))?;
+    if offset > 0 {
+        position += reader.read_until(b'\n', &mut buffer)? as u64;
+    }
+    loop {
+        buffer.clear();
+        let read = reader.read_until(b'\n', &mut buffer)?;
+        if read == 0 {
+            return Ok(None)
+        }
+        let line = String::from_utf8_lossy(&buffer);
+        if !line.trim().is_empty() && !is_transaction_line(&line) {
+            return Ok(Some((position, entry_date(&line).to_string())))
+        }
+        position += read as u64;
+    }
+}
+
+/// The offset of the first entry dated on or after `date`, found by binary
+/// search over the bytes of a ledger whose entries are in date order; the
+/// length of the file when there is none.
+pub fn seek_date(path: &Path, date: &str) -> io::Result<u64> {
+    let mut reader = BufReader::new(File::open(path)?);
+    let length = reader.get_ref().metadata()?.len();
+    let (mut low, mut high) = (0, length);
+    while low < high {
+        let middle = low + (high - low) / 2;
+        match entry_after(&mut reader, middle)? {
+            Some((_, found)) if found.as_str() < date => low = middle + 1,
+            _ => high = middle
+        }
+    }
+    entry_after(&mut reader, low).map(|found| found.map_or(length, |(offset, _)| offset))
+}
+
+#[cfg(test)]
+mod test {
+    use std::env;
+    use std::fs;
+    use std::path::PathBuf;
+    use super::{ Index, index_path, seek_date };
+
+    fn ledger(name: &str, contents: &str) -> PathBuf {
+        let path = env::temp_dir().join(format!("budget_index_{}_{}", name, std::process::id()));
+        fs::write(&path, contents).unwrap();
+        path
+    }
+
+    #[test]
+    fn finds_dates_by_binary_search() {
+        let contents = "2016-01-01|1000.00\n2016-02-01|1100.00\ntxn|2016-01-15|-20.00\n\
+                        2016-02-01|1150.00|reconciled\n2016-03-01|900.00\n";
+        let path = ledger("seek", contents);
+        let offset = |date| seek_date(&path, date).unwrap() as usize;
+
+        assert_eq!(offset("2015-12-01"), 0);
+        assert_eq!(&contents[offset("2016-01-02")..], &contents[19..]);
+        assert_eq!(&contents[offset("2016-02-01")..], &contents[19..]);
+        assert_eq!(&contents[offset("2016-02-02")..], "2016-03-01|900.00\n");
+        assert_eq!(offset("2016-03-02"), contents.len());
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn is_kept_until_the_ledger_changes() {
+        let path = ledger("stale", "2016-02-01|1000.00\n2016-01-01|900.00\n");
+        let index = Index::current(&path).unwrap();
+        assert!(!index.sorted && !index.transactions);
+        assert_eq!(Index::saved(&path), Some(index));
+
+        fs::write(&path, "2016-01-01|900.00\ntxn|2016-01-02|-5.00\n").unwrap();
+        assert_eq!(Index::saved(&path), None);
+        let index = Index::current(&path).unwrap();
+        assert!(index.sorted && index.transactions);
+        assert_eq!(index.last, Some("2016-01-01".to_string()));
+        fs::remove_file(index_path(&path)).unwrap();
+        fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,205 @@
+use std::fmt;
+use std::fs;
+use std::path::Path;
+use std::str::FromStr;
+
+use amount::canonical;
+use base::Error;
+use show::Delta;
+
+/// Price index values by period, `yyyy-mm` or `yyyy`, in period order.
+#[derive(PartialEq, Debug)]
+pub struct CpiTable {
+    periods: Vec<(String, f64)>
+}
+
+fn is_period(period: &str) -> bool {
+    let digits = |s: &str| !s.is_empty() && s.chars().all(|c| c.is_ascii_digit());
+    match period.split_once('-') {
+        Some((year, month)) => year.len() == 4 && digits(year) && month.len() == 2 && digits(month) &&
+                               ("01"..="12").contains(&month),
+        None => period.len() == 4 && digits(period)
+    }
+}
+
+impl CpiTable {
+    /// Reads a `period,value` pair a line, such as `2016-01,236.916`; tabs
+    /// or spaces may separate them instead. Blank lines, `#` comments and a
+    /// header line are skipped.
+    pub fn parse(text: &str) -> Result<CpiTable, String> {
+        let mut periods = Vec::new();
+        for (number, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue
+            }
+            let fields = line.split(|c: char| c == ',' || c.is_whitespace())
+                             .filter(|f| !f.is_empty())
+                             .collect::<Vec<&str>>();
+            let value = fields.get(1).and_then(|v| f64::from_str(v).ok()).filter(|v| *v > 0.0);
+            match value {
+                Some(value) if fields.len() == 2 && is_period(fields[0]) => periods.push((fields[0].to_string(), value)),
+                _ if number == 0 => continue,
+                _ => return Err(format!("Invalid CPI line {}: {}; must be a period such as 2016-01 or 2016 \
+                                         and a positive index value", number + 1, line))
+            }
+        }
+        if periods.is_empty() {
+            return Err("The CPI table has no values".to_string())
+        }
+        periods.sort_by(|a, b| a.0.cmp(&b.0));
+        Ok(CpiTable { periods })
+    }
+
+    /// The value for the latest period starting on or before `date`: the
+    /// entry's month, or its year in an annual table.
+    pub fn value_on(&self, date: &str) -> Option<f64> {
+        self.periods.iter().rev().find(|&(period, _)| period.as_str() <= date).map(|&(_, value)| value)
+    }
+
+    fn value_of(&self, period: &str) -> Option<f64> {
+        self.periods.iter().find(|&(p, _)| p == period).map(|&(_, value)| value)
+    }
+}
+
+fn cents(value: f64) -> f64 {
+    (value * 100.0).round() / 100.0
+}
+
+/// A step restated in dollars of the base period.
+#[derive(PartialEq, Debug)]
+pub struct RealDelta {
+    pub base: String,
+    pub start: f64,
+    pub end: f64,
+    pub nominal: f64
+}
+
+impl RealDelta {
+    pub fn delta(&self) -> f64 {
+        cents(self.end - self.start)
+    }
+
+    /// The balance grew, but by less than prices did.
+    pub fn lost_to_inflation(&self) -> bool {
+        self.nominal > 0.0 && self.delta() < 0.0
+    }
+}
+
+impl fmt::Display for RealDelta {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "real {}: {} -> {} | {}", self.base, canonical(self.start),
+                                                      canonical(self.end),
+                                                      canonical(self.delta()))?;
+        if self.lost_to_inflation() {
+            write!(f, " | lost to inflation")?;
+        }
+        Ok(())
+    }
+}
+
+/// Restates amounts in dollars of one period of a CPI table.
+pub struct Inflation {
+    table: CpiTable,
+    base: String,
+    base_value: f64
+}
+
+impl Inflation {
+    /// The base period must be in the table; without one the latest is
+    /// used.
+    pub fn new(table: CpiTable, base: Option<&str>) -> Result<Inflation, String> {
+        let base = base.map_or_else(|| table.periods[table.periods.len() - 1].0.clone(), |b| b.to_string());
+        let base_value = table.value_of(&base).ok_or_else(|| format!("No CPI value for the base period {}", base))?;
+        Ok(Inflation { table, base, base_value })
+    }
+
+    pub fn read(path: &Path, base: Option<&str>) -> Result<Inflation, Error> {
+        let text = fs::read_to_string(path).map_err(|e| {
+            println!("Could not read the CPI table {}: {}", path.display(), e);
+            Error::ReadError
+        })?;
+        CpiTable::parse(&text).and_then(|table| Inflation::new(table, base))
+                              .map_err(|message| {
+                                  println!("{}", message);
+                                  Error::InputError
+                              })
+    }
+
+    /// An amount from `date` in base period dollars.
+    pub fn restate(&self, amount: f64, date: &str) -> Result<f64, String> {
+        self.table.value_on(date)
+                  .map(|value| cents(amount * self.base_value / value))
+                  .ok_or_else(|| format!("No CPI value on or before {}", date))
+    }
+
+    pub fn restate_delta(&self, delta: &Delta) -> Result<RealDelta, String> {
+        Ok(RealDelta { base: self.base.clone(),
+                       start: self.restate(delta.start.amount(), &delta.start.date_string)?,
+                       end: self.restate(delta.end.amount(), &delta.end.date_string)?,
+                       nominal: delta.delta() })
+    }
+
+    /// Every step restated, or the first that the table does not cover.
+    pub fn restate_all(&self, deltas: &[Delta]) -> Result<Vec<RealDelta>, Error> {
+        deltas.iter()
+              .map(|delta| self.restate_delta(delta))
+              .collect::<Result<Vec<RealDelta>, String>>()
+              .map_err(|message| {
+                  println!("{}", message);
+                  Error::InputError
+              })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use base::Entry;
+    use show::Delta;
+    use super::{ CpiTable, Inflation };
+
+    fn table() -> CpiTable {
+        CpiTable::parse("period,cpi\n\
+                         # monthly CPI-U\n\
+                         2016-01,200.0\n\
+                         2016-02\t202.0\n\
+                         \n\
+                         2016-03 210.0\n").unwrap()
+    }
+
+    #[test]
+    fn reads_monthly_and_annual_tables() {
+        let monthly = table();
+        assert_eq!(monthly.value_on("2016-02-29"), Some(202.0));
+        assert_eq!(monthly.value_on("2016-01-01"), Some(200.0));
+        assert_eq!(monthly.value_on("2015-12-31"), None);
+
+        let annual = CpiTable::parse("2017,250\n2016,240\n").unwrap();
+        assert_eq!(annual.value_on("2016-07-04"), Some(240.0));
+        assert_eq!(annual.value_on("2019-01-01"), Some(250.0));
+
+        assert!(CpiTable::parse("2016-01,200\n2016-13,201\n").unwrap_err().contains("line 2"));
+        assert!(CpiTable::parse("2016-01,-5\n").is_err());
+        assert!(CpiTable::parse("period,cpi\n").is_err());
+    }
+
+    #[test]
+    fn restates_steps_in_base_period_dollars() {
+        let start = Entry::new("2016-01-15", "1000.00");
+        let end = Entry::new("2016-03-15", "1020.00");
+        let delta = Delta::new(&start, &end);
+
+        let latest = Inflation::new(table(), None).unwrap().restate_delta(&delta).unwrap();
+        assert_eq!((latest.start, latest.end, latest.delta()), (1050.0, 1020.0, -30.0));
+        assert!(latest.lost_to_inflation());
+        assert_eq!(latest.to_string(), "real 2016-03: 1050.00 -> 1020.00 | -30.00 | lost to inflation");
+
+        let january = Inflation::new(table(), Some("2016-01")).unwrap();
+        assert_eq!(january.restate(1020.0, "2016-03-15"), Ok(971.43));
+        assert!(january.restate(1000.0, "2015-12-01").is_err());
+        assert!(Inflation::new(table(), Some("2015-12")).is_err());
+
+        let fall = Entry::new("2016-03-20", "900.00");
+        assert!(!january.restate_delta(&Delta::new(&end, &fall)).unwrap().lost_to_inflation());
+    }
+}
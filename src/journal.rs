@@ -0,0 +1,439 @@
+extern crate clap;
+extern crate time;
+
+use std::env;
+use std::fmt;
+use std::io::{ self, ErrorKind };
+use std::path::{ Path, PathBuf };
+use std::str::FromStr;
+use clap::ArgMatches;
+use serde_json;
+
+use base::Error;
+use crypt;
+use history;
+use storage::{ self, Line, Storage };
+
+/// One line added, removed, or replaced; an edit has both sides.
+#[derive(PartialEq, Eq, Debug, Clone, Serialize, Deserialize)]
+pub struct Change {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub before: Option<Line>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub after: Option<Line>
+}
+
+impl Change {
+    fn reversed(&self) -> Self {
+        Change { before: self.after.clone(), after: self.before.clone() }
+    }
+}
+
+/// One operation on the ledger, kept as a JSON line in the journal.
+#[derive(PartialEq, Eq, Debug, Clone, Serialize, Deserialize)]
+pub struct Record {
+    pub id: usize,
+    pub time: String,
+    pub user: String,
+    pub operation: String,
+    pub changes: Vec<Change>,
+    /// Set on an undo to the id of the record it reverted.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub reverts: Option<usize>
+}
+
+impl fmt::Display for Record {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:>4}  {}  {}  {}", self.id, self.time, self.user, self.operation)?;
+        if let Some(id) = self.reverts {
+            write!(f, " #{}", id)?;
+        }
+        for change in &self.changes {
+            match (&change.before, &change.after) {
+                (Some(before), Some(after)) => write!(f, "\n      - {}\n      + {}", before, after)?,
+                (Some(before), None) => write!(f, "\n      - {}", before)?,
+                (None, Some(after)) => write!(f, "\n      + {}", after)?,
+                (None, None) => {}
+            }
+        }
+        Ok(())
+    }
+}
+
+/// The journal sits beside the ledger, so `~/.budget` is journaled in
+/// `~/.budget.journal`.
+pub fn journal_path(data_path: &Path) -> PathBuf {
+    let mut path = data_path.as_os_str().to_os_string();
+    path.push(".journal");
+    PathBuf::from(path)
+}
+
+pub fn read_journal(data_path: &Path) -> Result<Vec<Record>, Error> {
+    match crypt::read(&journal_path(data_path)) {
+        Ok(contents) => contents.lines()
+                                .filter(|line| !line.trim().is_empty())
+                                .map(|line| serde_json::from_str(line).map_err(|_| Error::ReadError))
+                                .collect(),
+        Err(ref e) if e.kind() == ErrorKind::NotFound => Ok(Vec::new()),
+        Err(e) => Err(crypt::read_error(e))
+    }
+}
+
+fn next_id(data_path: &Path) -> Result<usize, Error> {
+    read_journal(data_path).map(|records| records.last().map_or(1, |r| r.id + 1))
+}
+
+fn append_record(data_path: &Path, record: &Record) -> Result<bool, Error> {
+    let json = serde_json::to_string(record).map_err(|_| Error::WriteError)?;
+    // the journal holds balances too, so it is encrypted along with the ledger
+    crypt::append(&journal_path(data_path), &format!("{}\n", json), crypt::is_encrypted(data_path))
+          .map(|_| true)
+          .map_err(crypt::write_error)
+}
+
+fn user() -> String {
+    env::var("USER").or_else(|_| env::var("USERNAME"))
+                    .unwrap_or_else(|_| "unknown".to_string())
+}
+
+fn now() -> String {
+    format!("{}", time::now_utc().rfc3339())
+}
+
+/// The ledger's lines; none when there is no ledger yet.
+fn read_lines(data_path: &Path) -> io::Result<Vec<String>> {
+    match storage::open(data_path).load() {
+        Ok(contents) => Ok(contents.lines()
+                                   .iter()
+                                   .map(|line| line.to_string())
+                                   .filter(|line| !line.trim().is_empty())
+                                   .collect()),
+        Err(ref e) if e.kind() == ErrorKind::NotFound => Ok(Vec::new()),
+        Err(e) => Err(e)
+    }
+}
+
+/// The lines that differ between two versions of the ledger, with a removed
+/// and an added line of the same kind and date paired up as an edit.
+pub fn changes(before: &[String], after: &[String]) -> Vec<Change> {
+    let prefix = before.iter().zip(after).take_while(|(b, a)| b == a).count();
+    let suffix = before[prefix..].iter().rev()
+                                 .zip(after[prefix..].iter().rev())
+                                 .take_while(|(b, a)| b == a)
+                                 .count();
+    let mut added = after[prefix..after.len() - suffix].iter()
+                                                        .map(|l| Some(Line::parse(l)))
+                                                        .collect::<Vec<Option<Line>>>();
+    let mut removed = Vec::new();
+    for line in before[prefix..before.len() - suffix].iter().map(|l| Line::parse(l)) {
+        match added.iter().position(|a| a.as_ref() == Some(&line)) {
+            Some(i) => added[i] = None,
+            None => removed.push(line)
+        }
+    }
+    let mut changes = Vec::new();
+    for line in removed {
+        let edit = added.iter()
+                        .position(|a| a.as_ref().is_some_and(|a| a.same_kind(&line) && a.date() == line.date()));
+        changes.push(Change { after: edit.and_then(|i| added[i].take()), before: Some(line) });
+    }
+    changes.extend(added.into_iter().flatten().map(|line| Change { before: None, after: Some(line) }));
+    changes
+}
+
+/// The id for the next record; the ledger is not to be changed when the
+/// journal cannot be read.
+fn first_id(data_path: &Path) -> Result<usize, Error> {
+    next_id(data_path).inspect_err(|_| {
+        println!("Could not read the journal {}; the ledger was not changed", journal_path(data_path).display())
+    })
+}
+
+/// Journals `changes` as one `operation`, committing them when the ledger
+/// is versioned.
+fn journal(data_path: &Path, id: usize, operation: &str, changes: Vec<Change>) -> Result<(), Error> {
+    if changes.is_empty() {
+        return Ok(())
+    }
+    let record = Record { id,
+                          time: now(),
+                          user: user(),
+                          operation: operation.to_string(),
+                          changes,
+                          reverts: None };
+    append_record(data_path, &record)?;
+    history::commit(data_path, &[record]);
+    Ok(())
+}
+
+/// Appends `lines` to the ledger and journals them as one `operation`.
+/// Nothing else changes, so the ledger is not read.
+pub fn appended(data_path: &Path, operation: &str, lines: &[Line]) -> Result<bool, Error> {
+    let id = first_id(data_path)?;
+    let mut storage = storage::open(data_path);
+    let mut changes = Vec::new();
+    let mut result = Ok(true);
+    for line in lines {
+        if let Err(e) = storage.append(line) {
+            result = Err(crypt::write_error(e));
+            break
+        }
+        changes.push(Change { before: None, after: Some(line.clone()) });
+    }
+    journal(data_path, id, operation, changes)?;
+    result
+}
+
+/// Runs `write`, which may rewrite the ledger, and journals whatever it
+/// changed as one `operation`. The whole ledger is read before and after
+/// to find the changes; nothing is written when it cannot be read first.
+pub fn recorded<F>(data_path: &Path, operation: &str, write: F) -> Result<bool, Error>
+    where F: FnOnce() -> Result<bool, Error> {
+    let id = first_id(data_path)?;
+    let before = read_lines(data_path).map_err(|e| {
+        println!("Could not read {}: {}; the ledger was not changed", data_path.display(), e);
+        crypt::read_error(e)
+    })?;
+    let result = write();
+    let after = read_lines(data_path).map_err(|e| {
+        println!("Could not read {} back to journal the change: {}", data_path.display(), e);
+        crypt::read_error(e)
+    })?;
+    journal(data_path, id, operation, changes(&before, &after))?;
+    result
+}
+
+/// Makes `change` to the ledger lines: `before` must be there to be
+/// replaced or removed.
+fn apply(lines: &mut Vec<Line>, change: &Change) -> Result<(), String> {
+    if let Some(ref before) = change.before {
+        let i = lines.iter()
+                     .position(|l| l == before)
+                     .ok_or_else(|| format!("{} is no longer in the ledger", before))?;
+        lines.remove(i);
+    }
+    if let Some(ref after) = change.after {
+        lines.push(after.clone());
+    }
+    Ok(())
+}
+
+fn store(storage: &mut dyn Storage, change: &Change) -> io::Result<bool> {
+    match (&change.before, &change.after) {
+        (Some(before), Some(after)) => storage.update(before, after),
+        (Some(before), None) => storage.delete(before),
+        (None, Some(after)) => storage.insert(after).map(|_| true),
+        (None, None) => Ok(true)
+    }
+}
+
+/// The latest `n` records that can still be undone, most recent first:
+/// undos themselves and records already reverted are passed over.
+pub fn undoable(records: &[Record], n: usize) -> Vec<&Record> {
+    let reverted = records.iter().filter_map(|r| r.reverts).collect::<Vec<usize>>();
+    records.iter()
+           .rev()
+           .filter(|r| r.reverts.is_none() && !reverted.contains(&r.id))
+           .take(n)
+           .collect()
+}
+
+/// Reverts `records` in order, checking each against the ledger first. The
+/// ledger is only changed if every one of them applies.
+pub fn undo(data_path: &Path, records: &[&Record]) -> Result<Vec<Record>, String> {
+    let next = next_id(data_path).map_err(|_| format!("Could not read the journal {}; nothing was undone",
+                                                      journal_path(data_path).display()))?;
+    let mut storage = storage::open(data_path);
+    let mut lines = storage.load()
+                           .map(|contents| contents.lines())
+                           .map_err(|e| format!("Could not read {}: {}", data_path.display(), e))?;
+    for record in records {
+        for change in record.changes.iter().rev() {
+            apply(&mut lines, &change.reversed()).map_err(|e| format!("Cannot undo #{}: {}", record.id, e))?;
+        }
+    }
+    let undos = records.iter()
+                       .enumerate()
+                       .map(|(i, record)| Record { id: next + i,
+                                                   time: now(),
+                                                   user: user(),
+                                                   operation: "undo".to_string(),
+                                                   changes: record.changes.iter().rev().map(Change::reversed).collect(),
+                                                   reverts: Some(record.id) })
+                       .collect::<Vec<Record>>();
+    for change in undos.iter().flat_map(|undo| undo.changes.iter()) {
+        store(storage.as_mut(), change).map_err(|e| format!("Could not write {}: {}", data_path.display(), e))?;
+    }
+    for record in &undos {
+        append_record(data_path, record).map_err(|_| format!("Could not write {}", journal_path(data_path).display()))?;
+    }
+    history::commit(data_path, &undos);
+    Ok(undos)
+}
+
+pub fn run_log(data_path: &Path, matches: &ArgMatches) -> Result<bool, Error> {
+    let submatches = matches.subcommand_matches("log").ok_or(Error::InputError)?;
+    let records = read_journal(data_path)?;
+    let limit = submatches.value_of("num")
+                          .map_or(Ok(records.len()), usize::from_str)
+                          .map_err(|_| Error::InputError)?;
+    if records.is_empty() {
+        println!("No changes recorded in {}", journal_path(data_path).display());
+    }
+    for record in records.iter().rev().take(limit) {
+        println!("{}", record);
+    }
+    Ok(true)
+}
+
+pub fn run_undo(data_path: &Path, matches: &ArgMatches) -> Result<bool, Error> {
+    let submatches = matches.subcommand_matches("undo").ok_or(Error::InputError)?;
+    let n = submatches.value_of("count")
+                      .map_or(Ok(1), usize::from_str)
+                      .map_err(|_| Error::InputError)?;
+    let records = read_journal(data_path)?;
+    let targets = undoable(&records, n);
+    if targets.is_empty() {
+        println!("Nothing to undo");
+        return Ok(false)
+    }
+    if targets.len() < n {
+        println!("Only {} change(s) can be undone", targets.len());
+        return Err(Error::InputError)
+    }
+    match undo(data_path, &targets) {
+        Ok(undos) => {
+            for record in undos {
+                println!("{}", record);
+            }
+            Ok(true)
+        },
+        Err(message) => {
+            println!("{}", message);
+            Ok(false)
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::env;
+    use std::fs;
+    use std::path::PathBuf;
+    use base::Entry;
+    use txn::Transaction;
+    use add::{ write_entries, write_to_file };
+    use super::{ Change, Line, appended, changes, journal_path, read_journal, recorded, undo, undoable };
+
+    fn lines(text: &[&str]) -> Vec<String> {
+        text.iter().map(|l| l.to_string()).collect()
+    }
+
+    fn scratch(name: &str) -> PathBuf {
+        let path = env::temp_dir().join(format!("budget_journal_{}_{}", name, std::process::id()));
+        fs::write(&path, "2016-01-01|1000.00\n2016-02-01|1200.00\n").unwrap();
+        let _ = fs::remove_file(journal_path(&path));
+        path
+    }
+
+    fn clean(path: PathBuf) {
+        fs::remove_file(journal_path(&path)).unwrap();
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn pairs_removed_and_added_lines_on_the_same_date() {
+        let before = lines(&["2016-01-01|1000.00", "2016-02-01|1200.00", "2016-03-01|900.00"]);
+        let after = lines(&["2016-01-01|1000.00", "2016-02-01|1250.00", "2016-04-01|950.00",
+                            "txn|2016-03-15|-20.00"]);
+
+        assert_eq!(changes(&before, &after),
+                   vec![Change { before: Some(Line::Entry(Entry::new("2016-02-01", "1200.00"))),
+                                 after: Some(Line::Entry(Entry::new("2016-02-01", "1250.00"))) },
+                        Change { before: Some(Line::Entry(Entry::new("2016-03-01", "900.00"))), after: None },
+                        Change { before: None, after: Some(Line::Entry(Entry::new("2016-04-01", "950.00"))) },
+                        Change { before: None,
+                                 after: Some(Line::Transaction(Transaction::new("2016-03-15", "-20.00"))) }]);
+    }
+
+    #[test]
+    fn records_each_operation_and_undoes_the_latest() {
+        let path = scratch("undo");
+        recorded(&path, "add", || write_to_file(&Entry::new("2016-03-01", "900.00"), &path)).unwrap();
+        recorded(&path, "tui", || write_entries(&[Entry::new("2016-01-01", "1000.00"),
+                                                  Entry::new("2016-03-01", "900.00")], &path)).unwrap();
+        let records = read_journal(&path).unwrap();
+        assert_eq!(records.iter().map(|r| (r.id, r.operation.as_str())).collect::<Vec<_>>(),
+                   vec![(1, "add"), (2, "tui")]);
+
+        undo(&path, &undoable(&records, 1)).unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap(),
+                   "2016-01-01|1000.00\n2016-02-01|1200.00\n2016-03-01|900.00\n");
+
+        let records = read_journal(&path).unwrap();
+        assert_eq!(records[2].reverts, Some(2));
+        let next = undoable(&records, 5);
+        assert_eq!(next.iter().map(|r| r.id).collect::<Vec<usize>>(), vec![1]);
+        undo(&path, &next).unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap(), "2016-01-01|1000.00\n2016-02-01|1200.00\n");
+        clean(path);
+    }
+
+    #[test]
+    fn refuses_to_undo_over_unjournaled_changes() {
+        let path = scratch("refuse");
+        recorded(&path, "add", || write_to_file(&Entry::new("2016-03-01", "900.00"), &path)).unwrap();
+        fs::write(&path, "2016-01-01|1000.00\n2016-02-01|1200.00\n2016-03-01|950.00\n").unwrap();
+        let records = read_journal(&path).unwrap();
+
+        assert_eq!(undo(&path, &undoable(&records, 1)),
+                   Err("Cannot undo #1: 2016-03-01|900.00 is no longer in the ledger".to_string()));
+        assert_eq!(fs::read_to_string(&path).unwrap(),
+                   "2016-01-01|1000.00\n2016-02-01|1200.00\n2016-03-01|950.00\n");
+        assert_eq!(read_journal(&path).unwrap().len(), 1);
+        clean(path);
+    }
+
+    #[test]
+    fn refuses_to_change_the_ledger_without_a_readable_journal() {
+        let path = scratch("unreadable");
+        recorded(&path, "add", || write_to_file(&Entry::new("2016-03-01", "900.00"), &path)).unwrap();
+        let records = read_journal(&path).unwrap();
+        fs::write(journal_path(&path), "not json\n").unwrap();
+
+        assert!(recorded(&path, "add", || write_to_file(&Entry::new("2016-04-01", "950.00"), &path)).is_err());
+        assert!(undo(&path, &undoable(&records, 1)).unwrap_err().starts_with("Could not read the journal"));
+        assert_eq!(fs::read_to_string(&path).unwrap(),
+                   "2016-01-01|1000.00\n2016-02-01|1200.00\n2016-03-01|900.00\n");
+        clean(path);
+    }
+
+    #[test]
+    fn journals_appended_lines_as_they_are_written() {
+        let path = scratch("appended");
+        appended(&path, "add", &[Line::Entry(Entry::new("2016-03-01", "900.00")),
+                                 Line::Transaction(Transaction::new("2016-03-15", "-20.00"))]).unwrap();
+
+        assert_eq!(fs::read_to_string(&path).unwrap(),
+                   "2016-01-01|1000.00\n2016-02-01|1200.00\n2016-03-01|900.00\ntxn|2016-03-15|-20.00\n");
+        let records = read_journal(&path).unwrap();
+        assert_eq!(records.len(), 1);
+        assert_eq!(records[0].changes,
+                   vec![Change { before: None, after: Some(Line::Entry(Entry::new("2016-03-01", "900.00"))) },
+                        Change { before: None,
+                                 after: Some(Line::Transaction(Transaction::new("2016-03-15", "-20.00"))) }]);
+        clean(path);
+    }
+
+    #[test]
+    fn refuses_to_rewrite_a_ledger_it_cannot_read() {
+        let path = env::temp_dir().join(format!("budget_journal_directory_{}", std::process::id()));
+        fs::create_dir_all(&path).unwrap();
+        let mut written = false;
+
+        assert!(recorded(&path, "tui", || { written = true; Ok(true) }).is_err());
+        assert!(!written);
+        assert!(!journal_path(&path).exists());
+        fs::remove_dir(path).unwrap();
+    }
+}
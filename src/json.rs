@@ -0,0 +1,127 @@
+use std::collections::BTreeMap;
+use std::path::Path;
+use serde_json;
+
+use base::Entry;
+use txn::Transaction;
+
+pub const VERSION: u32 = 1;
+
+/// The whole ledger as JSON: every account with its entries and metadata.
+#[derive(PartialEq, Eq, Debug, Serialize, Deserialize)]
+pub struct Document {
+    pub version: u32,
+    #[serde(default)]
+    pub metadata: BTreeMap<String, String>,
+    pub accounts: Vec<Account>
+}
+
+#[derive(PartialEq, Eq, Debug, Serialize, Deserialize)]
+pub struct Account {
+    pub name: String,
+    #[serde(default)]
+    pub metadata: BTreeMap<String, String>,
+    pub entries: Vec<Entry>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub transactions: Vec<Transaction>
+}
+
+impl Document {
+    pub fn new(accounts: Vec<Account>) -> Self {
+        Document { version: VERSION, metadata: BTreeMap::new(), accounts }
+    }
+
+    /// Finds the account to import, which must be named unless it is the only one.
+    pub fn account(&self, name: Option<&str>) -> Result<&Account, String> {
+        let names = self.accounts.iter()
+                                 .map(|a| a.name.as_str())
+                                 .collect::<Vec<&str>>()
+                                 .join(", ");
+        match name {
+            Some(name) => self.accounts.iter()
+                                       .find(|a| a.name == name)
+                                       .ok_or(format!("No account {}; found {}", name, names)),
+            None if self.accounts.len() == 1 => Ok(&self.accounts[0]),
+            None => Err(format!("Choose an account with --account; found {}", names))
+        }
+    }
+}
+
+impl Account {
+    pub fn new<S>(name: S, entries: Vec<Entry>) -> Self
+        where S: Into<String> {
+        Account { name: name.into(), metadata: BTreeMap::new(), entries, transactions: Vec::new() }
+    }
+}
+
+/// Names an account after its ledger file, so `~/.budget` is `budget`.
+pub fn account_name(data_path: &Path) -> String {
+    data_path.file_name()
+             .map(|name| name.to_string_lossy().trim_start_matches('.').to_string())
+             .filter(|name| !name.is_empty())
+             .unwrap_or_else(|| "budget".to_string())
+}
+
+pub fn to_json(document: &Document) -> String {
+    serde_json::to_string_pretty(document).unwrap_or_default() + "\n"
+}
+
+pub fn from_json(contents: &str) -> Result<Document, String> {
+    let document = serde_json::from_str::<Document>(contents).map_err(|e| e.to_string())?;
+    if document.version > VERSION {
+        return Err(format!("Unsupported version {}; expected at most {}", document.version, VERSION))
+    }
+    Ok(document)
+}
+
+#[cfg(test)]
+mod test {
+    use std::path::Path;
+    use base::Entry;
+    use txn::Transaction;
+    use super::{ Account, Document, account_name, from_json, to_json };
+
+    #[test]
+    fn round_trips_accounts_and_metadata() {
+        let mut document = Document::new(vec![
+            Account::new("checking", vec![Entry::new("2016-01-01", "1000.00"),
+                                          Entry::new("2016-02-01", "1500.00")]),
+            Account::new("savings", vec![Entry::new("2016-01-01", "200")])
+        ]);
+        document.metadata.insert("exported".to_string(), "2016-02-02".to_string());
+        document.accounts[1].metadata.insert("bank".to_string(), "Credit Union".to_string());
+        document.accounts[0].transactions.push(Transaction::new("2016-01-15", "-45.20").with_payee("Diner")
+                                                                                       .with_category("dining"));
+
+        assert_eq!(from_json(&to_json(&document)), Ok(document));
+    }
+
+    #[test]
+    fn serializes_entries_with_ledger_fields() {
+        let document = Document::new(vec![Account::new("budget", vec![Entry::new("2016-01-01", "1000.00")])]);
+
+        assert_eq!(to_json(&document),
+                   "{\n  \"version\": 1,\n  \"metadata\": {},\n  \"accounts\": [\n    {\n      \
+                    \"name\": \"budget\",\n      \"metadata\": {},\n      \"entries\": [\n        \
+                    {\n          \"date\": \"2016-01-01\",\n          \"amount\": \"1000.00\"\n        \
+                    }\n      ]\n    }\n  ]\n}\n");
+    }
+
+    #[test]
+    fn selects_account_to_import() {
+        let document = from_json("{\"version\": 1, \"accounts\": [\
+                                    {\"name\": \"checking\", \"entries\": []},\
+                                    {\"name\": \"savings\", \"entries\": []}]}").unwrap();
+
+        assert_eq!(document.account(Some("savings")).map(|a| a.name.as_str()), Ok("savings"));
+        assert!(document.account(None).is_err());
+        assert!(document.account(Some("brokerage")).is_err());
+        assert!(from_json("{\"version\": 2, \"accounts\": []}").is_err());
+    }
+
+    #[test]
+    fn names_account_after_ledger_file() {
+        assert_eq!(account_name(Path::new("/home/stuart/.budget")), "budget");
+        assert_eq!(account_name(Path::new("/var/savings")), "savings");
+    }
+}
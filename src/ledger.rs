@@ -0,0 +1,184 @@
+use std::str::FromStr;
+
+use amount::normalize_amount;
+use base::{ Entry, Validation };
+use date::shift_date;
+
+#[derive(PartialEq, Eq, Debug, Clone, Copy)]
+pub enum LedgerFormat {
+    Ledger,
+    Hledger,
+    Beancount
+}
+
+impl FromStr for LedgerFormat {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, ()> {
+        match s {
+            "ledger" => Ok(LedgerFormat::Ledger),
+            "hledger" => Ok(LedgerFormat::Hledger),
+            "beancount" => Ok(LedgerFormat::Beancount),
+            _ => Err(())
+        }
+    }
+}
+
+/// Where the difference between one balance and the next is posted.
+const ADJUSTMENTS: &str = "Equity:Adjustments";
+
+/// Writes each entry as a dated balance on `account`, moved there from
+/// `Equity:Adjustments` so that the file checks on its own: a balance
+/// assignment in ledger and hledger, and in beancount a `pad` followed by a
+/// `balance` the next day, since beancount checks balances at the start of
+/// the day. With `open`, beancount's `open` directives come first.
+pub fn to_ledger(entries: &[Entry], format: LedgerFormat, account: &str, commodity: &str, open: bool) -> String {
+    let mut out = String::new();
+    if format == LedgerFormat::Beancount && open {
+        if let Some(first) = entries.first() {
+            out.push_str(&format!("{} open {} {}\n{} open {} {}\n\n", first.date_string, account, commodity,
+                                                                    first.date_string, ADJUSTMENTS, commodity));
+        }
+    }
+    for entry in entries {
+        let block = match format {
+            LedgerFormat::Ledger | LedgerFormat::Hledger => {
+                let date = if format == LedgerFormat::Ledger {
+                    entry.date_string.replace('-', "/")
+                } else {
+                    entry.date_string.clone()
+                };
+                format!("{} * Balance\n    {}  = {} {}\n    {}\n\n", date,
+                                                                   account,
+                                                                   entry.amount_string,
+                                                                   commodity,
+                                                                   ADJUSTMENTS)
+            },
+            LedgerFormat::Beancount => {
+                let next = shift_date(&entry.date_string, 0, 1).unwrap_or_else(|| entry.date_string.clone());
+                format!("{} pad {} {}\n{} balance {}  {} {}\n", entry.date_string,
+                                                                account,
+                                                                ADJUSTMENTS,
+                                                                next,
+                                                                account,
+                                                                entry.amount_string,
+                                                                commodity)
+            }
+        };
+        out.push_str(&block);
+    }
+    out
+}
+
+/// Reads balance assertions back from ledger, hledger or beancount files,
+/// limited to `account` when one is given. A beancount `balance` holds at
+/// the start of its day, so it is read as the balance of the day before.
+pub fn entries_from_ledger(contents: &str, account: Option<&str>) -> Vec<Entry> {
+    let mut entries = Vec::new();
+    let mut current_date: Option<String> = None;
+    for line in contents.lines() {
+        let content = line.split(';').next().unwrap_or("");
+        if content.trim().is_empty() {
+            continue
+        }
+        let indented = content.starts_with(' ') || content.starts_with('\t');
+        let words = content.split_whitespace().collect::<Vec<&str>>();
+
+        if !indented {
+            current_date = ledger_date(words[0]);
+            // 2016-01-01 balance Assets:Checking 1000.00 USD
+            if words.len() >= 4 && words[1] == "balance" && matches_account(words[2], account) {
+                let date = current_date.as_ref().and_then(|date| shift_date(date, 0, -1));
+                if let (Some(date), Some(amount)) = (date, amount_in(&words[3..])) {
+                    entries.push(Entry::new(date, amount));
+                }
+            }
+        } else if let (Some(date), Some(assertion)) = (current_date.clone(), content.find('=')) {
+            //     Assets:Checking  0 USD = 1000.00 USD
+            let posting_account = content[..assertion].trim()
+                                                      .split("  ")
+                                                      .next()
+                                                      .unwrap_or("");
+            let words = content[assertion + 1..].split_whitespace().collect::<Vec<&str>>();
+            if matches_account(posting_account, account) {
+                if let Some(amount) = amount_in(&words) {
+                    entries.push(Entry::new(date, amount));
+                }
+            }
+        }
+    }
+    entries
+}
+
+fn ledger_date(word: &str) -> Option<String> {
+    let date = word.replace('/', "-");
+    match Entry::new(date.as_str(), "0").validate() {
+        Validation::DateParseError => None,
+        _ => Some(date)
+    }
+}
+
+fn matches_account(found: &str, wanted: Option<&str>) -> bool {
+    wanted.is_none_or(|w| found.trim().eq_ignore_ascii_case(w))
+}
+
+fn amount_in(words: &[&str]) -> Option<String> {
+    words.iter().filter_map(|w| normalize_amount(w)).next()
+}
+
+#[cfg(test)]
+mod test {
+    use base::Entry;
+    use super::{ LedgerFormat, to_ledger, entries_from_ledger };
+
+    fn entries() -> Vec<Entry> {
+        vec![Entry::new("2016-01-01", "1000.00"),
+             Entry::new("2016-02-01", "1500.00")
+            ]
+    }
+
+    #[test]
+    fn exports_ledger_balance_assignments() {
+        assert_eq!(to_ledger(&entries(), LedgerFormat::Ledger, "Assets:Checking", "USD", true),
+                   "2016/01/01 * Balance\n    Assets:Checking  = 1000.00 USD\n    Equity:Adjustments\n\n\
+                    2016/02/01 * Balance\n    Assets:Checking  = 1500.00 USD\n    Equity:Adjustments\n\n");
+        assert!(to_ledger(&entries(), LedgerFormat::Hledger, "Assets:Checking", "USD", false)
+                    .starts_with("2016-01-01 * Balance\n    Assets:Checking  = 1000.00 USD\n    Equity:Adjustments\n"));
+    }
+
+    #[test]
+    fn exports_beancount_pads_and_next_day_balances() {
+        assert_eq!(to_ledger(&entries(), LedgerFormat::Beancount, "Assets:Checking", "USD", true),
+                   "2016-01-01 open Assets:Checking USD\n\
+                    2016-01-01 open Equity:Adjustments USD\n\n\
+                    2016-01-01 pad Assets:Checking Equity:Adjustments\n\
+                    2016-01-02 balance Assets:Checking  1000.00 USD\n\
+                    2016-02-01 pad Assets:Checking Equity:Adjustments\n\
+                    2016-02-02 balance Assets:Checking  1500.00 USD\n");
+        assert!(to_ledger(&entries(), LedgerFormat::Beancount, "Assets:Checking", "USD", false)
+                    .starts_with("2016-01-01 pad "));
+    }
+
+    #[test]
+    fn round_trips_every_format() {
+        for format in &[LedgerFormat::Ledger, LedgerFormat::Hledger, LedgerFormat::Beancount] {
+            let exported = to_ledger(&entries(), *format, "Assets:Checking", "USD", true);
+
+            assert_eq!(entries_from_ledger(&exported, None), entries());
+            assert_eq!(entries_from_ledger(&exported, Some("assets:checking")), entries());
+            assert_eq!(entries_from_ledger(&exported, Some("Assets:Savings")), vec![]);
+        }
+    }
+
+    #[test]
+    fn reads_assertions_among_other_postings() {
+        let journal = "; opening\n\
+                       2016/03/05 Groceries\n    \
+                           Expenses:Food  $50.00\n    \
+                           Assets:Checking  $-50.00 = $1,250.00 ; checked\n\n\
+                       2016-04-01 balance Assets:Savings  300 USD\n";
+
+        assert_eq!(entries_from_ledger(journal, Some("Assets:Checking")),
+                   vec![Entry::new("2016-03-05", "1250.00")]);
+    }
+}
@@ -1,12 +1,48 @@
+extern crate argon2;
+extern crate chacha20poly1305;
 extern crate clap;
+extern crate atty;
+extern crate crossterm;
+extern crate getrandom;
+extern crate rpassword;
+extern crate rusqlite;
+extern crate serde;
+#[macro_use]
+extern crate serde_derive;
+extern crate serde_json;
 
 use std::env;
 use std::process::exit;
-use clap::{ Arg, App, SubCommand };
+use clap::{ Arg, ArgGroup, App, AppSettings, SubCommand };
 
+mod amount;
 mod base;
+mod date;
 mod add;
 mod show;
+mod csv;
+mod ofx;
+mod import;
+mod ledger;
+mod export;
+mod json;
+mod txn;
+mod config;
+mod envelope;
+mod recurring;
+mod balance;
+mod reconcile;
+mod chart;
+mod report;
+mod serve;
+mod watch;
+mod tui;
+mod journal;
+mod history;
+mod crypt;
+mod storage;
+mod index;
+mod inflation;
 
 use base::{ filepath, Error };
 
@@ -20,16 +56,36 @@ fn main() {
                            .short("f")
                            .long("file")
                            .takes_value(true))
+                      .arg(Arg::with_name("config")
+                           .help("alternate config file [default: the ledger file with .conf appended]")
+                           .short("c")
+                           .long("config")
+                           .takes_value(true))
                       .subcommand(SubCommand::with_name("add")
-                                  .about("add an entry")
+                                  .about("add an entry; prompts for missing values on a terminal")
+                                  .setting(AppSettings::AllowLeadingHyphen)
                                   .arg(Arg::with_name("date")
-                                       .help("yyyy-mm-dd")
-                                       .index(1)
-                                       .required(true))
+                                       .help("yyyy-mm-dd, today, yesterday, -3d, last friday, Sep 1 2016")
+                                       .index(1))
                                   .arg(Arg::with_name("amount")
-                                       .help("float")
+                                       .help("balance, e.g. 1234.56, $1,234.56, (250.00) or 1200+35.50")
                                        .index(2)
-                                       .required(true)))
+                                       .requires("date")
+                                       .conflicts_with("delta"))
+                                  .arg(Arg::with_name("delta")
+                                       .help("change since the latest entry on or before the date (default today)")
+                                       .long("delta")
+                                       .takes_value(true))
+                                  .arg(Arg::with_name("note")
+                                       .help("free-text note explaining the entry")
+                                       .long("note")
+                                       .takes_value(true))
+                                  .arg(Arg::with_name("tag")
+                                       .help("tag for the entry; may be repeated")
+                                       .long("tag")
+                                       .takes_value(true)
+                                       .multiple(true)
+                                       .number_of_values(1)))
                       .subcommand(SubCommand::with_name("show")
                                   .about("show differences")
                                   .arg(Arg::with_name("num")
@@ -40,17 +96,303 @@ fn main() {
                                        .required_unless("date")
                                        .conflicts_with("date"))
                                   .arg(Arg::with_name("date")
-                                       .help("start date for entries")
+                                       .help("start date for entries, in any format add accepts")
                                        .short("d")
                                        .long("date")
                                        .takes_value(true)
+                                       .allow_hyphen_values(true)
                                        .required_unless("num")
                                        .conflicts_with("num"))
                                   .arg(Arg::with_name("agg")
                                        .help("aggregate entries rather than reporting each line")
                                        .short("a")
                                        .long("aggregate")
+                                       .takes_value(false))
+                                  .arg(Arg::with_name("tag")
+                                       .help("only show steps ending on an entry with this tag")
+                                       .long("tag")
+                                       .takes_value(true)
+                                       .conflicts_with("agg"))
+                                  .arg(Arg::with_name("real")
+                                       .help("also restate each step in constant dollars using this CPI table")
+                                       .long("real")
+                                       .takes_value(true))
+                                  .arg(Arg::with_name("base")
+                                       .help("CPI period whose dollars to restate in, such as 2016-01; \
+                                              defaults to the latest in the table")
+                                       .long("base")
+                                       .takes_value(true)
+                                       .requires("real"))
+                                  .arg(Arg::with_name("watch")
+                                       .help("show again whenever the ledger file changes")
+                                       .long("watch")
                                        .takes_value(false)))
+                      .subcommand(SubCommand::with_name("tui")
+                                  .about("browse and edit the ledger full screen"))
+                      .subcommand(SubCommand::with_name("chart")
+                                  .about("chart the balance in the terminal")
+                                  .arg(Arg::with_name("num")
+                                       .help("number of recent entries")
+                                       .short("n")
+                                       .long("number")
+                                       .takes_value(true)
+                                       .conflicts_with("date"))
+                                  .arg(Arg::with_name("date")
+                                       .help("start date for entries, in any format add accepts")
+                                       .short("d")
+                                       .long("date")
+                                       .takes_value(true)
+                                       .allow_hyphen_values(true))
+                                  .arg(Arg::with_name("sparkline")
+                                       .help("a single line of blocks, one per entry")
+                                       .long("sparkline")
+                                       .takes_value(false)
+                                       .conflicts_with("deltas"))
+                                  .arg(Arg::with_name("deltas")
+                                       .help("bars of the change at each entry instead of the balance")
+                                       .long("deltas")
+                                       .takes_value(false))
+                                  .arg(Arg::with_name("width")
+                                       .help("columns to fill [default: the terminal width, $COLUMNS or 80]")
+                                       .long("width")
+                                       .takes_value(true))
+                                  .arg(Arg::with_name("height")
+                                       .help("rows of the chart [default: 10]")
+                                       .long("height")
+                                       .takes_value(true)))
+                      .subcommand(SubCommand::with_name("report")
+                                  .about("write a self-contained HTML report with a chart, stats and alerts")
+                                  .arg(Arg::with_name("html")
+                                       .help("file to write the report to")
+                                       .long("html")
+                                       .takes_value(true)
+                                       .required(true))
+                                  .arg(Arg::with_name("num")
+                                       .help("number of recent entries")
+                                       .short("n")
+                                       .long("number")
+                                       .takes_value(true)
+                                       .conflicts_with("date"))
+                                  .arg(Arg::with_name("date")
+                                       .help("start date for entries, in any format add accepts")
+                                       .short("d")
+                                       .long("date")
+                                       .takes_value(true)
+                                       .allow_hyphen_values(true)))
+                      .subcommand(SubCommand::with_name("serve")
+                                  .about("serve the ledger as a JSON API")
+                                  .arg(Arg::with_name("port")
+                                       .help("port to listen on [default: 8080]")
+                                       .long("port")
+                                       .takes_value(true))
+                                  .arg(Arg::with_name("bind")
+                                       .help("address to listen on [default: 127.0.0.1]")
+                                       .long("bind")
+                                       .takes_value(true))
+                                  .arg(Arg::with_name("token")
+                                       .help("bearer token required to POST entries [default: $BUDGET_TOKEN]; without one the API is read only")
+                                       .long("token")
+                                       .takes_value(true)))
+                      .subcommand(SubCommand::with_name("balance")
+                                  .about("balance on any date, recorded or estimated")
+                                  .arg(Arg::with_name("on")
+                                       .help("date, in any format add accepts [default: today]")
+                                       .long("on")
+                                       .takes_value(true)
+                                       .allow_hyphen_values(true))
+                                  .arg(Arg::with_name("interpolation")
+                                       .help("estimate between entries by carrying the earlier balance forward or on a straight line [default: linear]")
+                                       .long("interpolation")
+                                       .takes_value(true)
+                                       .possible_values(&["step", "linear"])))
+                      .subcommand(SubCommand::with_name("reconcile")
+                                  .about("compare a statement balance with the ledger")
+                                  .setting(AppSettings::AllowLeadingHyphen)
+                                  .arg(Arg::with_name("date")
+                                       .help("statement date, in any format add accepts")
+                                       .index(1)
+                                       .required(true))
+                                  .arg(Arg::with_name("amount")
+                                       .help("statement balance")
+                                       .index(2)
+                                       .required(true))
+                                  .arg(Arg::with_name("accept")
+                                       .help("record the statement balance on its date and mark it reconciled")
+                                       .long("accept")
+                                       .takes_value(false)))
+                      .subcommand(SubCommand::with_name("log")
+                                  .about("list the changes made to the ledger, most recent first")
+                                  .arg(Arg::with_name("num")
+                                       .help("number of recent changes")
+                                       .short("n")
+                                       .long("number")
+                                       .takes_value(true)))
+                      .subcommand(SubCommand::with_name("undo")
+                                  .about("revert the most recent changes to the ledger")
+                                  .arg(Arg::with_name("count")
+                                       .help("number of changes to revert [default: 1]")
+                                       .index(1)))
+                      .subcommand(SubCommand::with_name("history")
+                                  .about("show how the entry on a date changed across git commits")
+                                  .arg(Arg::with_name("date")
+                                       .help("entry date, in any format add accepts")
+                                       .index(1)
+                                       .required(true)))
+                      .subcommand(SubCommand::with_name("encrypt")
+                                  .about("encrypt the ledger and its journal with a passphrase"))
+                      .subcommand(SubCommand::with_name("decrypt")
+                                  .about("store the ledger and its journal as plain text again"))
+                      .subcommand(SubCommand::with_name("convert")
+                                  .about("copy the ledger into a new file, as text or SQLite")
+                                  .arg(Arg::with_name("target")
+                                       .help("new ledger file; .db, .sqlite and .sqlite3 files are SQLite")
+                                       .index(1)
+                                       .required(true))
+                                  .arg(Arg::with_name("backend")
+                                       .help("storage for the new file, whatever its extension")
+                                       .long("to")
+                                       .takes_value(true)
+                                       .possible_values(&["text", "sqlite"])))
+                      .subcommand(SubCommand::with_name("txn")
+                                  .about("record transactions alongside balances")
+                                  .setting(AppSettings::SubcommandRequiredElseHelp)
+                                  .subcommand(SubCommand::with_name("add")
+                                              .about("add a transaction; negative for spending")
+                                              .setting(AppSettings::AllowLeadingHyphen)
+                                              .arg(Arg::with_name("date")
+                                                   .help("yyyy-mm-dd, today, yesterday, -3d, last friday, Sep 1 2016")
+                                                   .index(1)
+                                                   .required(true))
+                                              .arg(Arg::with_name("amount")
+                                                   .help("amount, e.g. -45.20 or (45.20)")
+                                                   .index(2)
+                                                   .required(true))
+                                              .arg(Arg::with_name("payee")
+                                                   .help("who was paid or paid you")
+                                                   .long("payee")
+                                                   .takes_value(true))
+                                              .arg(Arg::with_name("category")
+                                                   .help("spending category, e.g. groceries")
+                                                   .long("category")
+                                                   .takes_value(true))))
+                      .subcommand(SubCommand::with_name("envelope")
+                                  .about("report monthly category budgets against transactions")
+                                  .arg(Arg::with_name("month")
+                                       .help("any date in the month to report [default: today]")
+                                       .short("m")
+                                       .long("month")
+                                       .takes_value(true)
+                                       .allow_hyphen_values(true))
+                                  .subcommand(SubCommand::with_name("set")
+                                              .about("set a category's monthly limit")
+                                              .arg(Arg::with_name("category")
+                                                   .help("transaction category, e.g. groceries")
+                                                   .index(1)
+                                                   .required(true))
+                                              .arg(Arg::with_name("limit")
+                                                   .help("monthly limit, e.g. 600")
+                                                   .index(2)
+                                                   .required(true))
+                                              .arg(Arg::with_name("carry")
+                                                   .help("what happens to the remainder at month end")
+                                                   .long("carry")
+                                                   .takes_value(true)
+                                                   .possible_values(&["reset", "rollover", "surplus"]))))
+                      .subcommand(SubCommand::with_name("apply-recurring")
+                                  .about("add the transactions of recurring items that are due")
+                                  .arg(Arg::with_name("until")
+                                       .help("apply items due up to this date [default: today]")
+                                       .long("until")
+                                       .takes_value(true)
+                                       .allow_hyphen_values(true))
+                                  .arg(Arg::with_name("dry_run")
+                                       .help("list the transactions without writing")
+                                       .long("dry-run")
+                                       .takes_value(false)))
+                      .subcommand(SubCommand::with_name("import")
+                                  .about("import balances from a bank export or another ledger")
+                                  .group(ArgGroup::with_name("source")
+                                         .args(&["csv", "ofx", "ledger", "json"])
+                                         .required(true))
+                                  .arg(Arg::with_name("csv")
+                                       .help("CSV file with a date and a balance column")
+                                       .long("csv")
+                                       .takes_value(true))
+                                  .arg(Arg::with_name("ofx")
+                                       .help("OFX or QFX statement file")
+                                       .long("ofx")
+                                       .takes_value(true))
+                                  .arg(Arg::with_name("ledger")
+                                       .help("ledger, hledger or beancount file of balance assertions")
+                                       .long("ledger")
+                                       .takes_value(true))
+                                  .arg(Arg::with_name("json")
+                                       .help("JSON file written by export --json")
+                                       .long("json")
+                                       .takes_value(true))
+                                  .arg(Arg::with_name("account")
+                                       .help("account to import from a ledger or JSON file")
+                                       .long("account")
+                                       .takes_value(true))
+                                  .arg(Arg::with_name("available")
+                                       .help("also import OFX available balances; the ledger balance wins on shared dates")
+                                       .long("available")
+                                       .takes_value(false)
+                                       .requires("ofx"))
+                                  .arg(Arg::with_name("date_column")
+                                       .help("date column name or 1-based position [default: Date]")
+                                       .long("date-column")
+                                       .takes_value(true))
+                                  .arg(Arg::with_name("balance_column")
+                                       .help("balance column name or 1-based position [default: Balance]")
+                                       .long("balance-column")
+                                       .takes_value(true))
+                                  .arg(Arg::with_name("date_format")
+                                       .help("strptime format of the date column, e.g. %m/%d/%Y")
+                                       .long("date-format")
+                                       .takes_value(true))
+                                  .arg(Arg::with_name("delimiter")
+                                       .help("field delimiter [default: ,]")
+                                       .long("delimiter")
+                                       .takes_value(true))
+                                  .arg(Arg::with_name("month_end")
+                                       .help("keep only the last balance of each month")
+                                       .long("month-end")
+                                       .takes_value(false))
+                                  .arg(Arg::with_name("dry_run")
+                                       .help("preview the changes without writing")
+                                       .long("dry-run")
+                                       .takes_value(false)))
+                      .subcommand(SubCommand::with_name("export")
+                                  .about("export entries as balance assertions or JSON")
+                                  .arg(Arg::with_name("format")
+                                       .help("plain-text accounting format")
+                                       .long("format")
+                                       .takes_value(true)
+                                       .possible_values(&["ledger", "hledger", "beancount"])
+                                       .required_unless("json")
+                                       .conflicts_with("json"))
+                                  .arg(Arg::with_name("json")
+                                       .help("export the whole ledger as JSON")
+                                       .long("json")
+                                       .takes_value(false))
+                                  .arg(Arg::with_name("account")
+                                       .help("account name [default: Assets:Checking, or the file name for JSON]")
+                                       .long("account")
+                                       .takes_value(true))
+                                  .arg(Arg::with_name("commodity")
+                                       .help("commodity of the balances [default: USD]")
+                                       .long("commodity")
+                                       .takes_value(true))
+                                  .arg(Arg::with_name("open")
+                                       .help("open the account and Equity:Adjustments first (beancount)")
+                                       .long("open")
+                                       .takes_value(false))
+                                  .arg(Arg::with_name("output")
+                                       .help("write to a file instead of standard out")
+                                       .short("o")
+                                       .long("output")
+                                       .takes_value(true)))
                       .get_matches();
 
     let data_path = filepath(&matches, env::home_dir());
@@ -68,6 +410,57 @@ fn main() {
              Some("show") => {
                  show::run_show(&data_path, &matches)
              },
+             Some("import") => {
+                 import::run_import(&data_path, &matches)
+             },
+             Some("export") => {
+                 export::run_export(&data_path, &matches)
+             },
+             Some("tui") => {
+                 tui::run_tui(&data_path, &matches)
+             },
+             Some("chart") => {
+                 chart::run_chart(&data_path, &matches)
+             },
+             Some("report") => {
+                 report::run_report(&data_path, &matches)
+             },
+             Some("serve") => {
+                 serve::run_serve(&data_path, &matches)
+             },
+             Some("balance") => {
+                 balance::run_balance(&data_path, &matches)
+             },
+             Some("reconcile") => {
+                 reconcile::run_reconcile(&data_path, &matches)
+             },
+             Some("log") => {
+                 journal::run_log(&data_path, &matches)
+             },
+             Some("undo") => {
+                 journal::run_undo(&data_path, &matches)
+             },
+             Some("history") => {
+                 history::run_history(&data_path, &matches)
+             },
+             Some("encrypt") => {
+                 crypt::run_encrypt(&data_path, &matches)
+             },
+             Some("decrypt") => {
+                 crypt::run_decrypt(&data_path, &matches)
+             },
+             Some("convert") => {
+                 storage::run_convert(&data_path, &matches)
+             },
+             Some("txn") => {
+                 txn::run_txn(&data_path, &matches)
+             },
+             Some("envelope") => {
+                 envelope::run_envelope(&data_path, &matches)
+             },
+             Some("apply-recurring") => {
+                 recurring::run_apply_recurring(&data_path, &matches)
+             },
              _ => { Err(Error::InputError) }
          }});
 
@@ -80,6 +473,7 @@ fn exit_code(status: Result<bool, Error>) -> i32 {
         Ok(false) => 1,
         Err(Error::InputError) => 2,
         Err(Error::ReadError) => 3,
-        Err(Error::WriteError) => 4
+        Err(Error::WriteError) => 4,
+        Err(Error::PassphraseError) => 5
     }
 }
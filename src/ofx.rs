@@ -0,0 +1,115 @@
+use amount::normalize_amount;
+use base::{ Entry, Validation };
+
+/// Reads `LEDGERBAL` snapshots, and `AVAILBAL` ones when `include_available`
+/// is set, from OFX 1.x (SGML) or 2.x (XML) statements, such as QFX files.
+///
+/// Entries are returned in date order; where a ledger and an available
+/// balance share a date, the ledger balance comes last so it wins a dedupe.
+pub fn balances_from_ofx(contents: &str, include_available: bool) -> Vec<Entry> {
+    let mut balances = Vec::new();
+    let mut aggregate: Option<String> = None;
+    let mut amount: Option<String> = None;
+    let mut as_of: Option<String> = None;
+
+    for (tag, text) in tags(contents) {
+        match tag.as_str() {
+            "LEDGERBAL" | "AVAILBAL" => {
+                aggregate = Some(tag.clone());
+                amount = None;
+                as_of = None;
+            },
+            "/LEDGERBAL" | "/AVAILBAL" => {
+                let date = as_of.take().and_then(|d| ofx_date(&d));
+                let balance = amount.take().and_then(|a| normalize_amount(&a));
+                if let (Some(kind), Some(date), Some(balance)) = (aggregate.take(), date, balance) {
+                    balances.push((kind == "LEDGERBAL", Entry::new(date, balance)));
+                }
+            },
+            "BALAMT" if aggregate.is_some() => amount = Some(text),
+            "DTASOF" if aggregate.is_some() => as_of = Some(text),
+            _ => {}
+        }
+    }
+
+    let mut entries = balances.into_iter()
+                              .filter(|&(ledger, _)| ledger || include_available)
+                              .collect::<Vec<(bool, Entry)>>();
+    entries.sort_by(|a, b| (&a.1.date_string, a.0).cmp(&(&b.1.date_string, b.0)));
+    entries.into_iter().map(|(_, entry)| entry).collect()
+}
+
+// Yields each tag name (upper-cased, with a leading `/` for closing tags)
+// and the text that follows it up to the next tag.
+fn tags(contents: &str) -> Vec<(String, String)> {
+    contents.split('<')
+            .skip(1)
+            .filter_map(|chunk| {
+                let end = chunk.find('>')?;
+                let name = chunk[..end].trim();
+                if name.starts_with('?') || name.starts_with('!') {
+                    return None
+                }
+                Some((name.to_uppercase(), chunk[end + 1..].trim().to_string()))
+            })
+            .collect()
+}
+
+// OFX datetimes are `YYYYMMDD[HHMMSS[.XXX]][[offset:TZ]]`; the date is kept.
+fn ofx_date(input: &str) -> Option<String> {
+    let digits = input.get(..8).filter(|d| d.chars().all(|c| c.is_ascii_digit()))?;
+    let date = format!("{}-{}-{}", &digits[..4], &digits[4..6], &digits[6..]);
+    match Entry::new(date.as_str(), "0").validate() {
+        Validation::DateParseError => None,
+        _ => Some(date)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::fs::File;
+    use std::io::Read;
+    use base::Entry;
+    use super::{ balances_from_ofx, ofx_date };
+
+    fn fixture(name: &str) -> String {
+        let mut contents = String::new();
+        File::open(name).unwrap().read_to_string(&mut contents).unwrap();
+        contents
+    }
+
+    #[test]
+    fn reads_ledger_balance_from_sgml_statement() {
+        let contents = fixture("./test_data/import_statement.ofx");
+
+        assert_eq!(balances_from_ofx(&contents, false),
+                   vec![Entry::new("2016-02-29", "1250.00")]);
+        assert_eq!(balances_from_ofx(&contents, true),
+                   vec![Entry::new("2016-02-29", "1250.00"),
+                        Entry::new("2016-03-01", "1200.00")
+                       ]);
+    }
+
+    #[test]
+    fn reads_every_statement_from_xml_file() {
+        let contents = fixture("./test_data/import_statement.qfx");
+
+        assert_eq!(balances_from_ofx(&contents, false),
+                   vec![Entry::new("2016-01-31", "2500.00"),
+                        Entry::new("2016-02-15", "1300.00")
+                       ]);
+        assert_eq!(balances_from_ofx(&contents, true),
+                   vec![Entry::new("2016-01-31", "2450.00"),
+                        Entry::new("2016-01-31", "2500.00"),
+                        Entry::new("2016-02-15", "1300.00")
+                       ]);
+    }
+
+    #[test]
+    fn parses_ofx_datetimes() {
+        assert_eq!(ofx_date("20160229235959.000[-5:EST]"), Some("2016-02-29".to_string()));
+        assert_eq!(ofx_date("20160131"), Some("2016-01-31".to_string()));
+        assert_eq!(ofx_date("20161331"), None);
+        assert_eq!(ofx_date("2016"), None);
+    }
+}
@@ -0,0 +1,130 @@
+extern crate clap;
+
+use std::fmt;
+use std::path::Path;
+use clap::ArgMatches;
+
+use add::{ build_entry, invalid_message, write_entries };
+use amount::canonical;
+use balance::{ Balance, Interpolation, balance_on };
+use base::{ Entry, Error, Validation };
+use date;
+use journal;
+use show::read_entries;
+
+/// A statement balance set against what the ledger says for the same day.
+#[derive(PartialEq, Debug)]
+pub struct Reconciliation<'a> {
+    statement: &'a Entry,
+    ledger: Option<Balance>
+}
+
+impl<'a> Reconciliation<'a> {
+    pub fn new(entries: &[Entry], statement: &'a Entry) -> Self {
+        Reconciliation { statement, ledger: balance_on(entries, &statement.date_string, Interpolation::Linear) }
+    }
+
+    /// Statement minus ledger, to the cent; `None` without a ledger balance.
+    fn discrepancy(&self) -> Option<f64> {
+        self.ledger.as_ref().map(|b| ((self.statement.amount() - b.amount) * 100.0).round() / 100.0)
+    }
+
+    fn balanced(&self) -> bool {
+        self.discrepancy() == Some(0.0)
+    }
+}
+
+impl<'a> fmt::Display for Reconciliation<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}: statement {}", self.statement.date_string, canonical(self.statement.amount()))?;
+        match (&self.ledger, self.discrepancy()) {
+            (Some(ledger), Some(discrepancy)) if discrepancy != 0.0 =>
+                write!(f, " | ledger {} | discrepancy {}", ledger, canonical(discrepancy)),
+            (Some(ledger), _) => write!(f, " | ledger {} | balanced", ledger),
+            _ => write!(f, " | no ledger balance on or before this date")
+        }
+    }
+}
+
+/// Sets the statement balance on its date, replacing the amount of an entry
+/// already there but keeping its note and tags, and marks it reconciled.
+pub fn accept(mut entries: Vec<Entry>, statement: &Entry) -> Vec<Entry> {
+    match entries.iter_mut().rev().find(|e| e.date_string == statement.date_string) {
+        Some(entry) => {
+            entry.amount_string = statement.amount_string.clone();
+            entry.reconciled = true;
+        },
+        None => {
+            let position = entries.iter()
+                                  .position(|e| e.date_string > statement.date_string)
+                                  .unwrap_or(entries.len());
+            entries.insert(position, statement.clone().with_reconciled());
+        }
+    }
+    entries
+}
+
+pub fn run_reconcile(data_path: &Path, matches: &ArgMatches) -> Result<bool, Error> {
+    let submatches = matches.subcommand_matches("reconcile").ok_or(Error::InputError)?;
+    let statement = build_entry(submatches, &date::today())?;
+    match statement.validate() {
+        Validation::Valid => {},
+        invalid => {
+            println!("{}", invalid_message(&statement, &invalid));
+            return Err(Error::InputError)
+        }
+    }
+
+    let entries = read_entries(data_path)?;
+    let reconciliation = Reconciliation::new(&entries, &statement);
+    println!("{}", reconciliation);
+    if !submatches.is_present("accept") {
+        return Ok(reconciliation.balanced())
+    }
+    journal::recorded(data_path, "reconcile", || write_entries(&accept(entries, &statement), data_path))?;
+    println!("Reconciled {} at {}", statement.date_string, canonical(statement.amount()));
+    Ok(true)
+}
+
+#[cfg(test)]
+mod test {
+    use base::Entry;
+    use super::{ Reconciliation, accept };
+
+    fn entries() -> Vec<Entry> {
+        vec![Entry::new("2016-01-01", "1000.00"),
+             Entry::new("2016-01-31", "1300.00").with_note("paycheck")
+            ]
+    }
+
+    #[test]
+    fn reports_discrepancy_against_recorded_balance() {
+        let statement = Entry::new("2016-01-31", "1250.00");
+
+        assert_eq!(format!("{}", Reconciliation::new(&entries(), &statement)),
+                   "2016-01-31: statement 1250.00 | ledger 1300.00 recorded | discrepancy -50.00");
+    }
+
+    #[test]
+    fn reports_against_estimated_balance() {
+        let balanced = Entry::new("2016-01-16", "1150.00");
+        let early = Entry::new("2015-12-31", "900.00");
+
+        assert_eq!(format!("{}", Reconciliation::new(&entries(), &balanced)),
+                   "2016-01-16: statement 1150.00 | ledger 1150.00 estimated (linear) | balanced");
+        assert_eq!(format!("{}", Reconciliation::new(&entries(), &early)),
+                   "2015-12-31: statement 900.00 | no ledger balance on or before this date");
+    }
+
+    #[test]
+    fn accepting_replaces_or_inserts_reconciled_entry() {
+        let replaced = accept(entries(), &Entry::new("2016-01-31", "1250.00"));
+        let inserted = accept(entries(), &Entry::new("2016-01-16", "1150.00"));
+
+        assert_eq!(replaced[1], Entry::new("2016-01-31", "1250.00").with_note("paycheck")
+                                                                   .with_reconciled());
+        assert_eq!(inserted, vec![Entry::new("2016-01-01", "1000.00"),
+                                  Entry::new("2016-01-16", "1150.00").with_reconciled(),
+                                  Entry::new("2016-01-31", "1300.00").with_note("paycheck")]);
+    }
+}
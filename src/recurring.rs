@@ -0,0 +1,258 @@
+extern crate clap;
+
+use std::path::Path;
+use std::str::FromStr;
+use clap::ArgMatches;
+
+use amount::normalize_amount;
+use base::{ Entry, Error, Validation, date_value };
+use config::{ Config, config_path };
+use crypt;
+use date::{ self, format_date, shift_date };
+use journal;
+use storage::Line;
+use txn::{ Transaction, read_transactions };
+
+/// Recurring items live in sections named `[recurring <name>]`.
+pub const SECTION_PREFIX: &str = "recurring ";
+
+#[derive(PartialEq, Eq, Debug, Clone, Copy)]
+pub enum Cadence {
+    Weekly,
+    Biweekly,
+    Monthly,
+    Yearly
+}
+
+impl FromStr for Cadence {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, ()> {
+        match s {
+            "weekly" => Ok(Cadence::Weekly),
+            "biweekly" => Ok(Cadence::Biweekly),
+            "monthly" => Ok(Cadence::Monthly),
+            "yearly" => Ok(Cadence::Yearly),
+            _ => Err(())
+        }
+    }
+}
+
+/// A predictable transaction such as a paycheck or rent:
+///
+/// ```text
+/// [recurring rent]
+/// amount = -1200
+/// cadence = monthly
+/// start = 2016-01-01
+/// end = 2016-12-31
+/// category = housing
+/// ```
+///
+/// Monthly and yearly items fall on the day of `start`; `end` and `payee`
+/// are optional, and `category` defaults like any transaction's.
+#[derive(PartialEq, Debug)]
+pub struct Recurring {
+    pub name: String,
+    pub amount: String,
+    pub cadence: Cadence,
+    pub start: String,
+    pub end: Option<String>,
+    pub payee: Option<String>,
+    pub category: Option<String>
+}
+
+impl Recurring {
+    fn from_config(name: &str, pairs: &[(String, String)]) -> Result<Self, String> {
+        let value = |key: &str| pairs.iter()
+                                     .find(|(k, _)| k == key)
+                                     .map(|(_, v)| v.clone());
+        let amount = value("amount").and_then(|a| normalize_amount(&a))
+                                    .ok_or("amount must be a number like -1200")?;
+        let cadence = value("cadence").and_then(|c| Cadence::from_str(&c).ok())
+                                      .ok_or("cadence must be weekly, biweekly, monthly or yearly")?;
+        let start = value("start").filter(|s| valid_date(s))
+                                  .ok_or("start must be a yyyy-mm-dd date")?;
+        let end = match value("end") {
+            Some(ref end) if !valid_date(end) => return Err("end must be a yyyy-mm-dd date".to_string()),
+            end => end
+        };
+        Ok(Recurring { name: name.to_string(),
+                       amount,
+                       cadence,
+                       start,
+                       end,
+                       payee: value("payee"),
+                       category: value("category")
+                     })
+    }
+
+    /// Every date the item falls on from `start` up to and including
+    /// `until` or `end`, whichever is earlier. Each date is counted from
+    /// `start` so that month-end days are not lost after a short month.
+    pub fn dates(&self, until: &str) -> Vec<String> {
+        let last = match self.end {
+            Some(ref end) if end.as_str() < until => end.as_str(),
+            _ => until
+        };
+        (0..).map(|n| match self.cadence {
+                 Cadence::Weekly => shift_date(&self.start, 0, 7 * n),
+                 Cadence::Biweekly => shift_date(&self.start, 0, 14 * n),
+                 Cadence::Monthly => shift_date(&self.start, n, 0),
+                 Cadence::Yearly => shift_date(&self.start, 12 * n, 0)
+             })
+             .map_while(|date| date.filter(|d| d.as_str() <= last))
+             .collect()
+    }
+
+    fn transaction(&self, date: String) -> Transaction {
+        let mut transaction = Transaction::new(date, self.amount.clone()).with_recurring(self.name.as_str());
+        transaction.payee = self.payee.clone();
+        transaction.category = self.category.clone();
+        transaction
+    }
+}
+
+fn valid_date(date: &str) -> bool {
+    Entry::new(date, "0").validate() == Validation::Valid
+}
+
+pub fn recurring(config: &Config) -> Vec<Recurring> {
+    config.sections()
+          .iter()
+          .filter(|section| section.starts_with(SECTION_PREFIX))
+          .filter_map(|section| {
+              let name = section[SECTION_PREFIX.len()..].trim();
+              match Recurring::from_config(name, &config.section(section)) {
+                  Ok(item) => Some(item),
+                  Err(message) => {
+                      println!("Ignoring recurring {}; {}", name, message);
+                      None
+                  }
+              }
+          })
+          .collect()
+}
+
+/// Transactions due by `until` that have not been applied yet, in date
+/// order. An occurrence counts as applied when a transaction generated by
+/// the same item already exists on its date.
+pub fn due(items: &[Recurring], existing: &[Transaction], until: &str) -> Vec<Transaction> {
+    let mut pending = items.iter()
+                           .flat_map(|item| {
+                               item.dates(until)
+                                   .into_iter()
+                                   .filter(move |date| !existing.iter().any(|t| {
+                                       t.recurring.as_ref() == Some(&item.name) && &t.date_string == date
+                                   }))
+                                   .map(move |date| item.transaction(date))
+                           })
+                           .collect::<Vec<Transaction>>();
+    pending.sort_by(|a, b| a.date_string.cmp(&b.date_string));
+    pending
+}
+
+pub fn run_apply_recurring(data_path: &Path, matches: &ArgMatches) -> Result<bool, Error> {
+    let submatches = matches.subcommand_matches("apply-recurring").ok_or(Error::InputError)?;
+    let path = config_path(matches, data_path);
+    let items = recurring(&Config::read(&path)?);
+    if items.is_empty() {
+        println!("No recurring items in {}; add a [recurring <name>] section", path.display());
+        return Ok(false)
+    }
+
+    let until = date_value(submatches, "until")?.unwrap_or_else(|| format_date(&date::today()));
+    let pending = due(&items, &read_transactions(data_path).map_err(crypt::read_error)?, &until);
+    if pending.is_empty() {
+        println!("Nothing due by {}", until);
+        return Ok(true)
+    }
+    for transaction in &pending {
+        print!("apply {}", transaction);
+    }
+    if submatches.is_present("dry_run") {
+        return Ok(true)
+    }
+    journal::appended(data_path, "apply-recurring", &pending.into_iter().map(Line::from).collect::<Vec<Line>>())
+}
+
+#[cfg(test)]
+mod test {
+    use config::Config;
+    use txn::Transaction;
+    use super::{ Cadence, Recurring, due, recurring };
+
+    fn item(cadence: Cadence, start: &str) -> Recurring {
+        Recurring { name: "rent".to_string(),
+                    amount: "-1200.00".to_string(),
+                    cadence,
+                    start: start.to_string(),
+                    end: None,
+                    payee: None,
+                    category: Some("housing".to_string())
+                  }
+    }
+
+    #[test]
+    fn reads_recurring_sections() {
+        let config = Config::parse("[envelopes]\n\
+                                    groceries = 600\n\
+                                    [recurring paycheck]\n\
+                                    amount = $2,000.00\n\
+                                    cadence = biweekly\n\
+                                    start = 2016-01-08\n\
+                                    payee = Acme\n\
+                                    [recurring gym]\n\
+                                    amount = -40\n\
+                                    cadence = daily\n\
+                                    start = 2016-01-01\n");
+
+        assert_eq!(recurring(&config),
+                   vec![Recurring { name: "paycheck".to_string(),
+                                    amount: "2000.00".to_string(),
+                                    cadence: Cadence::Biweekly,
+                                    start: "2016-01-08".to_string(),
+                                    end: None,
+                                    payee: Some("Acme".to_string()),
+                                    category: None
+                                  }]);
+    }
+
+    #[test]
+    fn lists_dates_by_cadence() {
+        assert_eq!(item(Cadence::Biweekly, "2016-01-08").dates("2016-02-05"),
+                   vec!["2016-01-08", "2016-01-22", "2016-02-05"]);
+        assert_eq!(item(Cadence::Monthly, "2016-01-31").dates("2016-04-30"),
+                   vec!["2016-01-31", "2016-02-29", "2016-03-31", "2016-04-30"]);
+        assert_eq!(item(Cadence::Yearly, "2016-02-29").dates("2018-03-01"),
+                   vec!["2016-02-29", "2017-02-28", "2018-02-28"]);
+        assert_eq!(item(Cadence::Weekly, "2016-01-08").dates("2016-01-01"), Vec::<String>::new());
+    }
+
+    #[test]
+    fn stops_at_end_date() {
+        let mut rent = item(Cadence::Monthly, "2016-01-01");
+        rent.end = Some("2016-02-15".to_string());
+
+        assert_eq!(rent.dates("2016-06-01"), vec!["2016-01-01", "2016-02-01"]);
+    }
+
+    #[test]
+    fn skips_occurrences_already_applied() {
+        let items = vec![item(Cadence::Monthly, "2016-01-01")];
+        let existing = vec![Transaction::new("2016-01-01", "-1200.00").with_category("housing")
+                                                                      .with_recurring("rent"),
+                            Transaction::new("2016-02-01", "-1200.00").with_category("housing")];
+
+        let pending = due(&items, &existing, "2016-03-01");
+
+        assert_eq!(pending,
+                   vec![Transaction::new("2016-02-01", "-1200.00").with_category("housing")
+                                                                  .with_recurring("rent"),
+                        Transaction::new("2016-03-01", "-1200.00").with_category("housing")
+                                                                  .with_recurring("rent")]);
+        let mut applied = existing.clone();
+        applied.extend(pending);
+        assert_eq!(due(&items, &applied, "2016-03-01"), vec![]);
+    }
+}
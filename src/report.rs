@@ -0,0 +1,259 @@
+extern crate clap;
+
+use std::fs::File;
+use std::io::Write;
+use std::path::Path;
+use clap::ArgMatches;
+
+use amount::canonical;
+use base::{ Entry, Error, date_value };
+use crypt;
+use date::days_between;
+use show::{ Delta, deltas, explain, read_selected };
+use txn::{ Transaction, read_transactions };
+
+const SVG_WIDTH: f64 = 640.0;
+const SVG_HEIGHT: f64 = 240.0;
+const MARGIN: f64 = 40.0;
+
+const STYLE: &str = "body { font-family: sans-serif; max-width: 48em; margin: 2em auto; color: #222; }\n\
+                     table { border-collapse: collapse; width: 100%; }\n\
+                     th, td { padding: 0.25em 0.5em; border-bottom: 1px solid #ddd; text-align: right; }\n\
+                     th:first-child, td:first-child, td.note { text-align: left; }\n\
+                     .down { color: #b00; }\n\
+                     .alerts li { color: #b00; }\n\
+                     svg text { font-size: 11px; fill: #555; }\n\
+                     svg polyline { fill: none; stroke: #36c; stroke-width: 2; }\n\
+                     svg circle { fill: #fff; stroke: #36c; stroke-width: 2; }\n\
+                     svg circle.reconciled { fill: #36c; }\n";
+
+/// Summary figures for the period covered by `entries`.
+#[derive(PartialEq, Debug, Serialize)]
+pub struct Stats {
+    pub opening: f64,
+    pub closing: f64,
+    pub lowest: f64,
+    pub highest: f64,
+    pub largest_increase: f64,
+    pub largest_decrease: f64
+}
+
+impl Stats {
+    pub fn new(entries: &[Entry], deltas: &[Delta]) -> Self {
+        let amounts = entries.iter().map(|e| e.amount()).collect::<Vec<f64>>();
+        let changes = deltas.iter().map(|d| d.delta()).collect::<Vec<f64>>();
+        Stats { opening: amounts[0],
+                closing: amounts[amounts.len() - 1],
+                lowest: amounts.iter().cloned().fold(f64::INFINITY, f64::min),
+                highest: amounts.iter().cloned().fold(f64::NEG_INFINITY, f64::max),
+                largest_increase: changes.iter().cloned().fold(0.0, f64::max),
+                largest_decrease: changes.iter().cloned().fold(0.0, f64::min)
+              }
+    }
+
+    fn rows(&self) -> Vec<(&'static str, f64)> {
+        vec![("Opening balance", self.opening),
+             ("Closing balance", self.closing),
+             ("Net change", self.closing - self.opening),
+             ("Lowest balance", self.lowest),
+             ("Highest balance", self.highest),
+             ("Largest increase", self.largest_increase),
+             ("Largest decrease", self.largest_decrease)]
+    }
+}
+
+/// The point of the budget is a balance that holds steady or grows, so
+/// every step that lost money is called out, as is any negative balance.
+pub fn alerts(entries: &[Entry], deltas: &[Delta]) -> Vec<String> {
+    let negative = entries.iter()
+                          .filter(|e| e.amount() < 0.0)
+                          .map(|e| format!("Balance was negative on {}: {}", e.date_string, canonical(e.amount())));
+    let decreases = deltas.iter()
+                          .filter(|d| d.delta() < 0.0)
+                          .map(|d| format!("Balance fell by {} from {} to {}", canonical(-d.delta()),
+                                                                             d.start.date_string,
+                                                                             d.end.date_string));
+    negative.chain(decreases).collect()
+}
+
+fn escape_html(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+/// An inline SVG of the balance against time; reconciled entries are
+/// drawn as filled points.
+pub fn balance_svg(entries: &[Entry]) -> String {
+    let first = &entries[0].date_string;
+    let last = &entries[entries.len() - 1].date_string;
+    let span = days_between(first, last).unwrap_or(0).max(1) as f64;
+    let lowest = entries.iter().map(|e| e.amount()).fold(f64::INFINITY, f64::min);
+    let highest = entries.iter().map(|e| e.amount()).fold(f64::NEG_INFINITY, f64::max);
+    let range = if highest > lowest { highest - lowest } else { 1.0 };
+    let points = entries.iter()
+                        .map(|e| {
+                            let days = days_between(first, &e.date_string).unwrap_or(0) as f64;
+                            let x = MARGIN * 2.0 + days / span * (SVG_WIDTH - MARGIN * 3.0);
+                            let y = MARGIN + (highest - e.amount()) / range * (SVG_HEIGHT - MARGIN * 2.0);
+                            (x, y, e.reconciled)
+                        })
+                        .collect::<Vec<(f64, f64, bool)>>();
+
+    let mut svg = format!("<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{}\" height=\"{}\" \
+                           viewBox=\"0 0 {} {}\" role=\"img\" aria-label=\"Balance from {} to {}\">\n",
+                          SVG_WIDTH, SVG_HEIGHT, SVG_WIDTH, SVG_HEIGHT, first, last);
+    svg.push_str(&format!("<text x=\"{}\" y=\"{}\" text-anchor=\"end\">{}</text>\n",
+                          MARGIN * 2.0 - 8.0, MARGIN + 4.0, canonical(highest)));
+    svg.push_str(&format!("<text x=\"{}\" y=\"{}\" text-anchor=\"end\">{}</text>\n",
+                          MARGIN * 2.0 - 8.0, SVG_HEIGHT - MARGIN + 4.0, canonical(lowest)));
+    svg.push_str(&format!("<text x=\"{}\" y=\"{}\">{}</text>\n", MARGIN * 2.0, SVG_HEIGHT - 12.0, first));
+    svg.push_str(&format!("<text x=\"{}\" y=\"{}\" text-anchor=\"end\">{}</text>\n",
+                          SVG_WIDTH - MARGIN, SVG_HEIGHT - 12.0, last));
+    let line = points.iter()
+                     .map(|&(x, y, _)| format!("{:.1},{:.1}", x, y))
+                     .collect::<Vec<String>>();
+    svg.push_str(&format!("<polyline points=\"{}\"/>\n", line.join(" ")));
+    for &(x, y, reconciled) in &points {
+        svg.push_str(&format!("<circle cx=\"{:.1}\" cy=\"{:.1}\" r=\"3\"{}/>\n", x, y,
+                              if reconciled { " class=\"reconciled\"" } else { "" }));
+    }
+    svg.push_str("</svg>\n");
+    svg
+}
+
+fn delta_table(deltas: &[Delta], transactions: &[Transaction]) -> String {
+    let explained = !transactions.is_empty();
+    let mut table = String::from("<table class=\"deltas\">\n<thead><tr><th>From</th><th>To</th><th>Start</th>\
+                                  <th>End</th><th>Change</th>");
+    if explained {
+        table.push_str("<th>Explained</th><th>Unexplained</th>");
+    }
+    table.push_str("<th>Note</th></tr></thead>\n<tbody>\n");
+    for delta in deltas {
+        let change = delta.delta();
+        table.push_str(&format!("<tr><td>{}</td><td>{}</td><td>{}</td><td>{}</td><td{}>{}</td>",
+                                delta.start.date_string,
+                                delta.end.date_string,
+                                canonical(delta.start.amount()),
+                                canonical(delta.end.amount()),
+                                if change < 0.0 { " class=\"down\"" } else { "" },
+                                canonical(change)));
+        if explained {
+            let explanation = explain(delta, transactions);
+            table.push_str(&format!("<td>{}</td><td>{}</td>", canonical(explanation.explained),
+                                                              canonical(explanation.unexplained)));
+        }
+        let annotation = delta.end.note.iter()
+                                       .cloned()
+                                       .chain(delta.end.tags.iter().map(|t| format!("#{}", t)))
+                                       .collect::<Vec<String>>();
+        table.push_str(&format!("<td class=\"note\">{}</td></tr>\n", escape_html(&annotation.join(" "))));
+    }
+    table.push_str("</tbody>\n</table>\n");
+    table
+}
+
+/// A self-contained page: styles inline, the chart as inline SVG, and no
+/// scripts or external resources, so it can be archived or mailed as is.
+pub fn to_html(entries: &[Entry], deltas: &[Delta], transactions: &[Transaction]) -> String {
+    let first = &entries[0].date_string;
+    let last = &entries[entries.len() - 1].date_string;
+    let mut html = format!("<!DOCTYPE html>\n<html lang=\"en\">\n<head>\n<meta charset=\"utf-8\">\n\
+                            <title>Budget report {} to {}</title>\n<style>\n{}</style>\n</head>\n<body>\n\
+                            <h1>Budget report</h1>\n<p>{} to {}</p>\n",
+                           first, last, STYLE, first, last);
+
+    html.push_str("<h2>Alerts</h2>\n");
+    let alerts = alerts(entries, deltas);
+    if alerts.is_empty() {
+        html.push_str("<p>No alerts.</p>\n");
+    } else {
+        html.push_str("<ul class=\"alerts\">\n");
+        for alert in alerts {
+            html.push_str(&format!("<li>{}</li>\n", alert));
+        }
+        html.push_str("</ul>\n");
+    }
+
+    html.push_str("<h2>Balance</h2>\n");
+    html.push_str(&balance_svg(entries));
+
+    html.push_str("<h2>Stats</h2>\n<table class=\"stats\">\n");
+    for (name, value) in Stats::new(entries, deltas).rows() {
+        html.push_str(&format!("<tr><th>{}</th><td>{}</td></tr>\n", name, canonical(value)));
+    }
+    html.push_str("</table>\n");
+
+    html.push_str("<h2>Changes</h2>\n");
+    html.push_str(&delta_table(deltas, transactions));
+    html.push_str("</body>\n</html>\n");
+    html
+}
+
+pub fn run_report(data_path: &Path, matches: &ArgMatches) -> Result<bool, Error> {
+    let submatches = matches.subcommand_matches("report").ok_or(Error::InputError)?;
+    date_value(submatches, "date")?;
+    let path = submatches.value_of("html").ok_or(Error::InputError)?;
+    let filtered = &read_selected(data_path, submatches).map_err(crypt::read_error)?;
+    if filtered.len() < 2 {
+        println!("Need at least two entries for a report");
+        return Ok(false)
+    }
+    let html = to_html(filtered, &deltas(filtered, submatches), &read_transactions(data_path).map_err(crypt::read_error)?);
+    File::create(path).and_then(|mut f| f.write_all(html.as_bytes()))
+                      .map_err(|_| Error::WriteError)?;
+    println!("Wrote report to {}", path);
+    Ok(true)
+}
+
+#[cfg(test)]
+mod test {
+    use base::Entry;
+    use show::delta_by_line;
+    use txn::Transaction;
+    use super::{ alerts, escape_html, to_html };
+
+    fn entries() -> Vec<Entry> {
+        vec![Entry::new("2016-01-01", "1000.00").with_reconciled(),
+             Entry::new("2016-02-01", "1500.00"),
+             Entry::new("2016-03-01", "1250.00").with_note("car <repair>").with_tags(&["car"]),
+             Entry::new("2016-04-01", "1400.00")
+            ]
+    }
+
+    #[test]
+    fn alerts_on_decreases_and_negative_balances() {
+        let entries = vec![Entry::new("2016-01-01", "100.00"),
+                           Entry::new("2016-02-01", "-20.00"),
+                           Entry::new("2016-03-01", "50.00")];
+
+        assert_eq!(alerts(&entries, &delta_by_line(&entries)),
+                   vec!["Balance was negative on 2016-02-01: -20.00",
+                        "Balance fell by 120.00 from 2016-01-01 to 2016-02-01"]);
+    }
+
+    #[test]
+    fn escapes_notes() {
+        assert_eq!(escape_html("a < b & \"c\""), "a &lt; b &amp; &quot;c&quot;");
+    }
+
+    #[test]
+    fn html_report_matches_snapshot() {
+        let entries = entries();
+
+        assert_eq!(to_html(&entries, &delta_by_line(&entries), &[]),
+                   include_str!("../test_data/report.html"));
+    }
+
+    #[test]
+    fn html_report_with_transactions_matches_snapshot() {
+        let entries = entries();
+        let transactions = vec![Transaction::new("2016-01-15", "2000.00"),
+                                Transaction::new("2016-02-20", "-300.00")];
+
+        assert_eq!(to_html(&entries, &delta_by_line(&entries), &transactions),
+                   include_str!("../test_data/report_transactions.html"));
+    }
+}
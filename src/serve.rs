@@ -0,0 +1,496 @@
+extern crate clap;
+extern crate serde_json;
+
+use std::collections::BTreeMap;
+use std::env;
+use std::io::{ self, BufRead, BufReader, Read, Write };
+use std::net::{ SocketAddr, TcpListener, TcpStream };
+use std::path::{ Path, PathBuf };
+use std::time::Duration;
+use clap::ArgMatches;
+use serde::Serialize;
+
+use add::{ invalid_message, normalized_amount };
+use amount::canonical;
+use base::{ Entry, Error, Validation };
+use date::{ self, normalize_date, invalid_date_message };
+use journal;
+use report::Stats;
+use show::{ Delta, delta_by_line, read_entries };
+use storage::{ self, Line };
+use watch::{ Stamp, stamp };
+
+const DEFAULT_BIND: &str = "127.0.0.1";
+const DEFAULT_PORT: &str = "8080";
+const MAX_BODY: usize = 64 * 1024;
+const MAX_LINE: usize = 8 * 1024;
+const MAX_HEADERS: usize = 100;
+const MAX_HEAD: usize = 32 * 1024;
+
+/// The ledger being served. The last range asked for is kept, and read
+/// again whenever the file changes so that edits from other commands show
+/// up.
+pub struct Ledger {
+    path: PathBuf,
+    stamp: Option<Stamp>,
+    range: (Option<String>, Option<String>),
+    entries: Vec<Entry>
+}
+
+impl Ledger {
+    pub fn new(path: &Path) -> Self {
+        Ledger { path: path.to_path_buf(), stamp: None, range: (None, None), entries: Vec::new() }
+    }
+
+    /// Entries dated within `from` and `to`, both inclusive when given.
+    fn between(&mut self, from: Option<String>, to: Option<String>) -> &[Entry] {
+        let stamp = stamp(&self.path);
+        let range = (from, to);
+        if stamp.is_none() || stamp != self.stamp || range != self.range {
+            self.entries = storage::open(&self.path).query(range.0.as_deref(), range.1.as_deref())
+                                                    .unwrap_or_default();
+            self.stamp = stamp;
+            self.range = range;
+        }
+        &self.entries
+    }
+}
+
+pub struct Request {
+    pub method: String,
+    pub path: String,
+    pub query: BTreeMap<String, String>,
+    pub headers: BTreeMap<String, String>,
+    pub body: String
+}
+
+#[derive(PartialEq, Eq, Debug)]
+pub struct Response {
+    pub status: u16,
+    pub body: String
+}
+
+impl Response {
+    fn json<T: Serialize>(status: u16, value: &T) -> Self {
+        Response { status, body: serde_json::to_string(value).unwrap_or_default() }
+    }
+
+    fn error(status: u16, message: &str) -> Self {
+        let mut body = BTreeMap::new();
+        body.insert("error", message);
+        Response::json(status, &body)
+    }
+
+    fn reason(&self) -> &'static str {
+        match self.status {
+            200 => "OK",
+            201 => "Created",
+            400 => "Bad Request",
+            401 => "Unauthorized",
+            403 => "Forbidden",
+            404 => "Not Found",
+            405 => "Method Not Allowed",
+            413 => "Payload Too Large",
+            414 => "URI Too Long",
+            421 => "Misdirected Request",
+            431 => "Request Header Fields Too Large",
+            _ => "Internal Server Error"
+        }
+    }
+
+    fn write_to<W: Write>(&self, out: &mut W) -> std::io::Result<()> {
+        write!(out, "HTTP/1.1 {} {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\n\
+                     Connection: close\r\n\r\n{}", self.status, self.reason(), self.body.len(), self.body)
+    }
+}
+
+/// A step as the API reports it, with the ledger's own date and amount text.
+#[derive(Serialize)]
+struct DeltaBody {
+    from: String,
+    to: String,
+    start: String,
+    end: String,
+    change: String
+}
+
+impl<'a, 'b> From<&'b Delta<'a>> for DeltaBody {
+    fn from(delta: &'b Delta<'a>) -> Self {
+        DeltaBody { from: delta.start.date_string.clone(),
+                    to: delta.end.date_string.clone(),
+                    start: delta.start.amount_string.clone(),
+                    end: delta.end.amount_string.clone(),
+                    change: canonical(delta.delta())
+                  }
+    }
+}
+
+fn hex_digit(byte: u8) -> Option<u8> {
+    (byte as char).to_digit(16).map(|d| d as u8)
+}
+
+fn percent_decode(text: &str) -> String {
+    let bytes = text.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let escaped = match bytes.get(i + 1..i + 3) {
+            Some(&[high, low]) if bytes[i] == b'%' => hex_digit(high).and_then(|h| hex_digit(low).map(|l| h * 16 + l)),
+            _ => None
+        };
+        match (escaped, bytes[i]) {
+            (Some(byte), _) => {
+                decoded.push(byte);
+                i += 2;
+            },
+            (None, b'+') => decoded.push(b' '),
+            (None, byte) => decoded.push(byte)
+        }
+        i += 1;
+    }
+    String::from_utf8_lossy(&decoded).into_owned()
+}
+
+/// One line of at most `limit` bytes, counting its newline; `None` when it
+/// is longer.
+fn read_line_within<R: BufRead>(reader: &mut R, limit: usize) -> io::Result<Option<String>> {
+    let mut line = Vec::new();
+    reader.by_ref().take(limit as u64 + 1).read_until(b'\n', &mut line)?;
+    if line.len() > limit {
+        return Ok(None)
+    }
+    Ok(Some(String::from_utf8_lossy(&line).into_owned()))
+}
+
+/// Reads one HTTP/1.1 request, or the error response when it is malformed
+/// or larger than the limits above.
+pub fn read_request<R: BufRead>(reader: &mut R) -> Result<Request, Response> {
+    let malformed = |_| Response::error(400, "Malformed request");
+    let line = read_line_within(reader, MAX_LINE).map_err(malformed)?
+                                                 .ok_or_else(|| Response::error(414, "Request line too long"))?;
+    let mut parts = line.split_whitespace();
+    let (method, target) = match (parts.next(), parts.next()) {
+        (Some(method), Some(target)) => (method.to_string(), target),
+        _ => return Err(Response::error(400, "Malformed request"))
+    };
+    let (path, query_string) = target.split_once('?').unwrap_or((target, ""));
+    let query = query_string.split('&')
+                            .filter_map(|pair| pair.split_once('='))
+                            .map(|(k, v)| (percent_decode(k), percent_decode(v)))
+                            .collect();
+
+    let mut headers = BTreeMap::new();
+    let (mut count, mut head) = (0, line.len());
+    loop {
+        let header = read_line_within(reader, MAX_LINE).map_err(malformed)?
+                                                       .ok_or_else(|| Response::error(431, "Header line too long"))?;
+        if header.trim().is_empty() {
+            break
+        }
+        count += 1;
+        head += header.len();
+        if count > MAX_HEADERS || head > MAX_HEAD {
+            return Err(Response::error(431, "Too many or too large headers"))
+        }
+        if let Some((name, value)) = header.split_once(':') {
+            headers.insert(name.trim().to_lowercase(), value.trim().to_string());
+        }
+    }
+
+    let length = headers.get("content-length").and_then(|l| l.parse::<usize>().ok()).unwrap_or(0);
+    if length > MAX_BODY {
+        return Err(Response::error(413, "Request body too large"))
+    }
+    let mut body = vec![0; length];
+    reader.read_exact(&mut body).map_err(malformed)?;
+    Ok(Request { method,
+                 path: path.to_string(),
+                 query,
+                 headers,
+                 body: String::from_utf8_lossy(&body).into_owned()
+               })
+}
+
+/// `from` and `to` query dates, in any format `add` accepts.
+fn query_date(request: &Request, name: &str) -> Result<Option<String>, Response> {
+    match request.query.get(name) {
+        Some(input) => normalize_date(input, &date::today())
+                           .map(Some)
+                           .map_err(|e| Response::error(400, &invalid_date_message(input, &e))),
+        None => Ok(None)
+    }
+}
+
+/// Normalizes a posted entry the way `add` normalizes its arguments.
+fn posted_entry(body: &str) -> Result<Entry, Response> {
+    let mut entry = serde_json::from_str::<Entry>(body).map_err(|e| {
+        Response::error(400, &format!("Invalid JSON entry: {}", e))
+    })?;
+    entry.date_string = normalize_date(&entry.date_string, &date::today()).map_err(|e| {
+        Response::error(400, &invalid_date_message(&entry.date_string, &e))
+    })?;
+    entry.amount_string = normalized_amount(&entry.amount_string).map_err(|message| Response::error(400, &message))?;
+    match entry.validate() {
+        Validation::Valid => Ok(entry),
+        invalid => Err(Response::error(400, &invalid_message(&entry, &invalid)))
+    }
+}
+
+/// Compares in time that depends only on the length of `given`, so that a
+/// wrong token does not reveal how much of it was right.
+fn same_token(given: &str, token: &str) -> bool {
+    let expected = token.as_bytes();
+    let mut difference = given.len() ^ expected.len();
+    for (i, byte) in given.bytes().enumerate() {
+        difference |= (byte ^ expected[i % expected.len().max(1)]) as usize;
+    }
+    difference == 0
+}
+
+fn authorized(request: &Request, token: Option<&str>) -> Result<(), Response> {
+    let token = token.ok_or_else(|| Response::error(403, "Writes are disabled; start serve with --token"))?;
+    match request.headers.get("authorization").and_then(|header| header.strip_prefix("Bearer ")) {
+        Some(given) if !token.is_empty() && same_token(given, token) => Ok(()),
+        _ => Err(Response::error(401, "Missing or wrong bearer token"))
+    }
+}
+
+/// Whether the request was addressed to this server: a page on another
+/// site that rebinds its name to this address still sends its own name.
+fn addressed_here(request: &Request, bound: &SocketAddr) -> bool {
+    let host = match request.headers.get("host") {
+        Some(host) => host.to_lowercase(),
+        None => return true
+    };
+    let port = bound.port();
+    host == bound.to_string() ||
+        ["localhost", "127.0.0.1"].iter().any(|name| host == *name || host == format!("{}:{}", name, port))
+}
+
+pub fn handle(ledger: &mut Ledger, request: &Request, token: Option<&str>) -> Response {
+    let range = query_date(request, "from").and_then(|from| query_date(request, "to").map(|to| (from, to)));
+    let (from, to) = match range {
+        Ok(range) => range,
+        Err(response) => return response
+    };
+    let entries = &ledger.between(from, to).to_vec();
+
+    match (request.method.as_str(), request.path.as_str()) {
+        ("GET", "/entries") => Response::json(200, &entries),
+        ("GET", "/deltas") => Response::json(200, &delta_by_line(entries).iter()
+                                                                         .map(DeltaBody::from)
+                                                                         .collect::<Vec<DeltaBody>>()),
+        ("GET", "/aggregate") if entries.len() > 1 =>
+            Response::json(200, &DeltaBody::from(&Delta::new(&entries[0], &entries[entries.len() - 1]))),
+        ("GET", "/stats") if !entries.is_empty() =>
+            Response::json(200, &Stats::new(entries, &delta_by_line(entries))),
+        ("GET", "/aggregate") | ("GET", "/stats") => Response::error(404, "Not enough entries in range"),
+        ("POST", "/entries") => {
+            let stored = authorized(request, token).and_then(|_| posted_entry(&request.body))
+                                                   .and_then(|entry| {
+                journal::appended(&ledger.path, "serve", &[Line::from(entry.clone())]).map(|_| entry)
+                                                   .map_err(|_| Response::error(500, "Could not write the ledger"))
+            });
+            match stored {
+                Ok(entry) => Response::json(201, &entry),
+                Err(response) => response
+            }
+        },
+        (_, "/entries") | (_, "/deltas") | (_, "/aggregate") | (_, "/stats") =>
+            Response::error(405, "Method not allowed"),
+        _ => Response::error(404, "No such endpoint")
+    }
+}
+
+fn respond(stream: TcpStream, bound: &SocketAddr, ledger: &mut Ledger, token: Option<&str>) {
+    let _ = stream.set_read_timeout(Some(Duration::from_secs(5)));
+    let mut reader = BufReader::new(&stream);
+    let response = match read_request(&mut reader) {
+        Ok(ref request) if !addressed_here(request, bound) => Response::error(421, "Unknown Host"),
+        Ok(request) => handle(ledger, &request, token),
+        Err(response) => response
+    };
+    let _ = response.write_to(&mut &stream);
+}
+
+/// Answers requests one at a time until the listener fails.
+pub fn serve(listener: TcpListener, data_path: &Path, token: Option<String>) {
+    let bound = match listener.local_addr() {
+        Ok(bound) => bound,
+        Err(_) => return
+    };
+    let mut ledger = Ledger::new(data_path);
+    for stream in listener.incoming().map_while(Result::ok) {
+        respond(stream, &bound, &mut ledger, token.as_deref());
+    }
+}
+
+pub fn run_serve(data_path: &Path, matches: &ArgMatches) -> Result<bool, Error> {
+    let submatches = matches.subcommand_matches("serve").ok_or(Error::InputError)?;
+    let address = format!("{}:{}", submatches.value_of("bind").unwrap_or(DEFAULT_BIND),
+                                   submatches.value_of("port").unwrap_or(DEFAULT_PORT));
+    let token = submatches.value_of("token")
+                          .map(|t| t.to_string())
+                          .or_else(|| env::var("BUDGET_TOKEN").ok())
+                          .filter(|t| !t.is_empty());
+    // asks for the passphrase of an encrypted ledger before serving it
+    read_entries(data_path)?;
+    let listener = TcpListener::bind(&address).map_err(|e| {
+        println!("Could not listen on {}: {}", address, e);
+        Error::InputError
+    })?;
+    println!("Serving {} on http://{}{}", data_path.display(),
+                                          address,
+                                          if token.is_none() { " (read only; no --token)" } else { "" });
+    serve(listener, data_path, token);
+    Ok(true)
+}
+
+#[cfg(test)]
+mod test {
+    use std::env;
+    use std::fs;
+    use std::io::{ Cursor, Read, Write };
+    use std::net::{ TcpListener, TcpStream };
+    use std::path::PathBuf;
+    use std::thread;
+    use journal::journal_path;
+    use super::{ MAX_LINE, percent_decode, read_request, same_token, serve };
+
+    /// Starts a server on a free local port over a scratch copy of `contents`.
+    fn start(name: &str, contents: &str, token: Option<&str>) -> (String, PathBuf) {
+        let path = env::temp_dir().join(format!("budget_serve_{}_{}", name, std::process::id()));
+        fs::write(&path, contents).unwrap();
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap().to_string();
+        let served = path.clone();
+        let token = token.map(|t| t.to_string());
+        thread::spawn(move || serve(listener, &served, token));
+        (address, path)
+    }
+
+    fn request(address: &str, raw: &str) -> String {
+        let mut stream = TcpStream::connect(address).unwrap();
+        stream.write_all(raw.as_bytes()).unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).unwrap();
+        response
+    }
+
+    fn body(response: &str) -> &str {
+        response.split_once("\r\n\r\n").map_or("", |(_, body)| body)
+    }
+
+    const LEDGER: &str = "2016-01-01|1000.00\n2016-02-01|1500.00\n2016-03-01|1250.00\n";
+
+    #[test]
+    fn serves_entries_deltas_and_aggregate() {
+        let (address, path) = start("read", LEDGER, None);
+
+        let entries = request(&address, &format!("GET /entries?from=2016-02-01 HTTP/1.1\r\nHost: {}\r\n\r\n", address));
+        assert!(entries.starts_with("HTTP/1.1 200 OK\r\n"));
+        assert_eq!(body(&entries),
+                   "[{\"date\":\"2016-02-01\",\"amount\":\"1500.00\"},{\"date\":\"2016-03-01\",\"amount\":\"1250.00\"}]");
+
+        let deltas = request(&address, "GET /deltas?to=Feb%201%202016 HTTP/1.1\r\n\r\n");
+        assert_eq!(body(&deltas),
+                   "[{\"from\":\"2016-01-01\",\"to\":\"2016-02-01\",\"start\":\"1000.00\",\"end\":\"1500.00\",\"change\":\"500.00\"}]");
+
+        let aggregate = request(&address, "GET /aggregate HTTP/1.1\r\n\r\n");
+        assert_eq!(body(&aggregate),
+                   "{\"from\":\"2016-01-01\",\"to\":\"2016-03-01\",\"start\":\"1000.00\",\"end\":\"1250.00\",\"change\":\"250.00\"}");
+
+        let stats = request(&address, "GET /stats HTTP/1.1\r\n\r\n");
+        assert!(body(&stats).contains("\"largest_decrease\":-250.0"));
+
+        assert!(request(&address, "GET /deltas?from=13/1/2016 HTTP/1.1\r\n\r\n").starts_with("HTTP/1.1 400"));
+        assert!(request(&address, "GET /nothing HTTP/1.1\r\n\r\n").starts_with("HTTP/1.1 404"));
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn posts_entries_with_token_and_reloads() {
+        let (address, path) = start("write", LEDGER, Some("secret"));
+        let post = |auth: &str, json: &str| {
+            request(&address, &format!("POST /entries HTTP/1.1\r\n{}Content-Length: {}\r\n\r\n{}",
+                                       auth, json.len(), json))
+        };
+
+        assert!(post("", "{\"date\":\"2016-04-01\",\"amount\":\"1400\"}").starts_with("HTTP/1.1 401"));
+        assert!(post("Authorization: Bearer secret\r\n", "{\"date\":\"2016-04-01\",\"amount\":\"lots\"}")
+                    .starts_with("HTTP/1.1 400"));
+
+        let created = post("Authorization: Bearer secret\r\n", "{\"date\":\"2016-04-01\",\"amount\":\"$1,400.00\"}");
+        assert!(created.starts_with("HTTP/1.1 201 Created\r\n"));
+        assert_eq!(body(&created), "{\"date\":\"2016-04-01\",\"amount\":\"1400.00\"}");
+        assert!(fs::read_to_string(&path).unwrap().ends_with("2016-04-01|1400.00\n"));
+
+        fs::write(&path, "2016-01-01|1000.00\n2016-05-01|900.00\n").unwrap();
+        let aggregate = request(&address, "GET /aggregate HTTP/1.1\r\n\r\n");
+        assert!(body(&aggregate).contains("\"to\":\"2016-05-01\""));
+        fs::remove_file(journal_path(&path)).unwrap();
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn refuses_writes_without_a_token() {
+        let (address, path) = start("readonly", LEDGER, None);
+        let json = "{\"date\":\"2016-04-01\",\"amount\":\"1400\"}";
+
+        let response = request(&address, &format!("POST /entries HTTP/1.1\r\nContent-Length: {}\r\n\r\n{}",
+                                                  json.len(), json));
+
+        assert!(response.starts_with("HTTP/1.1 403"));
+        assert_eq!(fs::read_to_string(&path).unwrap(), LEDGER);
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn refuses_requests_for_other_hosts() {
+        let (address, path) = start("host", LEDGER, Some("secret"));
+        let port = address.rsplit(':').next().unwrap();
+        let json = "{\"date\":\"2016-04-01\",\"amount\":\"1400\"}";
+
+        assert!(request(&address, &format!("GET /entries HTTP/1.1\r\nHost: localhost:{}\r\n\r\n", port))
+                    .starts_with("HTTP/1.1 200"));
+        assert!(request(&address, "GET /entries HTTP/1.1\r\nHost: 127.0.0.1\r\n\r\n").starts_with("HTTP/1.1 200"));
+        assert!(request(&address, &format!("GET /entries HTTP/1.1\r\nHost: evil.example:{}\r\n\r\n", port))
+                    .starts_with("HTTP/1.1 421"));
+        let response = request(&address, &format!("POST /entries HTTP/1.1\r\nHost: evil.example\r\n\
+                                                   Authorization: Bearer secret\r\nContent-Length: {}\r\n\r\n{}",
+                                                  json.len(), json));
+        assert!(response.starts_with("HTTP/1.1 421"));
+        assert_eq!(fs::read_to_string(&path).unwrap(), LEDGER);
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn compares_tokens_whole() {
+        assert!(same_token("secret", "secret"));
+        assert!(!same_token("secreT", "secret"));
+        assert!(!same_token("secret2", "secret"));
+        assert!(!same_token("secre", "secret"));
+        assert!(!same_token("", "secret"));
+    }
+
+    #[test]
+    fn limits_the_request_head() {
+        let status = |raw: String| read_request(&mut Cursor::new(raw)).err().map(|r| r.status);
+        let long = "x".repeat(MAX_LINE);
+
+        assert_eq!(status("GET /entries HTTP/1.1\r\nHost: x\r\n\r\n".to_string()), None);
+        assert_eq!(status(format!("GET /{} HTTP/1.1\r\n\r\n", long)), Some(414));
+        assert_eq!(status(format!("GET / HTTP/1.1\r\nX: {}\r\n\r\n", long)), Some(431));
+        assert_eq!(status(format!("GET / HTTP/1.1\r\n{}\r\n", "X: y\r\n".repeat(101))), Some(431));
+        assert_eq!(status(format!("GET / HTTP/1.1\r\n{}\r\n", format!("X: {}\r\n", &long[..4096]).repeat(9))),
+                   Some(431));
+        assert_eq!(status("POST / HTTP/1.1\r\nContent-Length: 70000\r\n\r\n".to_string()), Some(413));
+        assert_eq!(status("nonsense\r\n\r\n".to_string()), Some(400));
+    }
+
+    #[test]
+    fn decodes_query_values() {
+        assert_eq!(percent_decode("Sep+1%2C%202016"), "Sep 1, 2016");
+        assert_eq!(percent_decode("100%"), "100%");
+    }
+}
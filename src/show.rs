@@ -1,73 +1,151 @@
 extern crate time;
 
 use std::fmt;
-use std::fs::{ OpenOptions, File };
-use std::io::{ BufRead, BufReader, Error as ioError };
+use std::io::{ Error as ioError, ErrorKind };
 use std::path::Path;
 use std::str::FromStr;
+use std::time::Duration;
 use clap::ArgMatches;
 
-use base::{ Entry, Error };
+use base::{ Entry, Error, date_value };
+use amount::canonical;
+use crypt;
+use inflation::Inflation;
+use storage;
+use txn::{ Transaction, between, read_transactions };
+use watch::Watcher;
+
+const WATCH_POLL: Duration = Duration::from_millis(250);
+const WATCH_SETTLE: Duration = Duration::from_millis(300);
 
 #[derive(PartialEq, Eq, Debug)]
-struct Delta<'a> {
-    start: &'a Entry,
-    end: &'a Entry
+pub struct Delta<'a> {
+    pub start: &'a Entry,
+    pub end: &'a Entry
 }
 
 impl<'a> Delta<'a> {
-    fn new(start: &'a Entry, end: &'a Entry) -> Self {
+    pub fn new(start: &'a Entry, end: &'a Entry) -> Self {
         Delta { start: start, end: end }
     }
 
-    fn delta(&self) -> f64 {
+    pub fn delta(&self) -> f64 {
         self.end.amount() - self.start.amount()
     }
 }
 
 impl<'a> fmt::Display for Delta<'a> {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        write!(f, "{} -> {}: {} -> {} | {}", self.start.date_string,
-                                             self.end.date_string,
-                                             self.start.amount_string,
-                                             self.end.amount_string,
-                                             self.delta())
+        write!(f, "{}{} -> {}{}: {} -> {} | {}", self.start.date_string,
+                                                 reconciled_marker(self.start),
+                                                 self.end.date_string,
+                                                 reconciled_marker(self.end),
+                                                 self.start.amount_string,
+                                                 self.end.amount_string,
+                                                 self.delta())?;
+        let annotation = self.end.note.iter()
+                                      .map(|note| note.replace('\n', " "))
+                                      .chain(self.end.tags.iter().map(|t| format!("#{}", t)))
+                                      .collect::<Vec<String>>();
+        if !annotation.is_empty() {
+            write!(f, " | {}", annotation.join(" "))?;
+        }
+        Ok(())
     }
 }
 
+/// Reconciled dates are starred, as cleared postings are in ledger files.
+fn reconciled_marker(entry: &Entry) -> &'static str {
+    if entry.reconciled { "*" } else { "" }
+}
+
 pub fn run_show(data_path: &Path, matches: &ArgMatches) -> Result<bool, Error> {
-    matches.subcommand_matches("show")
-           .ok_or(Error::InputError)
-           .and_then(|submatches|
-                read_file(data_path).map(|entries| {
-                    let filtered = filter_entries(&entries, &submatches);
-                    if submatches.is_present("agg") {
-                        println!("{}", aggregate_delta(filtered));
-                    } else {
-                        for delta in delta_by_line(filtered) {
-                            println!("{}", delta);
-                        }
-                    }
-                    true
-                }).map_err(|_| Error::ReadError))
-}
-
-fn read_file(file_path: &Path) -> Result<Vec<Entry>, ioError> {
-    OpenOptions::new()
-                .read(true)
-                .open(file_path)
-                .map(|f| entries_from_file(&f))
-}
-
-fn entries_from_file(f: &File) -> Vec<Entry> {
-    BufReader::new(f).lines()
-                     .filter_map(|l|
-                        l.map(|line| Entry::from_line(line)).ok()
-                     )
-                     .collect::<Vec<Entry>>()
-}
-
-fn filter_entries<'a>(entries: &'a [Entry], submatches: &ArgMatches) -> &'a [Entry] {
+    let submatches = matches.subcommand_matches("show").ok_or(Error::InputError)?;
+    date_value(submatches, "date")?;
+    if !submatches.is_present("watch") {
+        return print_show(data_path, submatches)
+    }
+
+    let mut watcher = Watcher::new(data_path, WATCH_POLL, WATCH_SETTLE);
+    loop {
+        // clear the screen and home the cursor before each render
+        print!("\x1b[2J\x1b[H");
+        println!("Watching {}; Ctrl-C to stop", data_path.display());
+        if print_show(data_path, submatches).is_err() {
+            println!("Could not read {}", data_path.display());
+        }
+        watcher.wait();
+    }
+}
+
+fn print_show(data_path: &Path, submatches: &ArgMatches) -> Result<bool, Error> {
+    let inflation = match submatches.value_of("real") {
+        Some(table) => Some(Inflation::read(Path::new(table), submatches.value_of("base"))?),
+        None => None
+    };
+    let entries = read_selected(data_path, submatches).map_err(crypt::read_error)?;
+    let transactions = read_transactions(data_path).map_err(crypt::read_error)?;
+    let deltas = deltas(&entries, submatches);
+    let real = match inflation {
+        Some(inflation) => inflation.restate_all(&deltas)?,
+        None => Vec::new()
+    };
+    for (i, delta) in deltas.iter().enumerate() {
+        println!("{}", delta);
+        if let Some(real) = real.get(i) {
+            println!("    {}", real);
+        }
+        if !transactions.is_empty() {
+            println!("    {}", explain(delta, &transactions));
+        }
+    }
+    Ok(true)
+}
+
+/// The steps `show` reports: one per line, or a single aggregate with `-a`,
+/// limited to those ending on `--tag`. There are none without entries.
+pub fn deltas<'a>(filtered: &'a [Entry], submatches: &ArgMatches) -> Vec<Delta<'a>> {
+    if submatches.is_present("agg") {
+        aggregate_delta(filtered).into_iter().collect()
+    } else {
+        tagged(delta_by_line(filtered), submatches.value_of("tag"))
+    }
+}
+
+/// The balance entries in the ledger, from whichever storage it uses.
+pub fn read_file(file_path: &Path) -> Result<Vec<Entry>, ioError> {
+    storage::open(file_path).load().map(|contents| contents.entries)
+}
+
+/// `read_file` for commands that write: a ledger that does not exist yet
+/// has no entries, but one that cannot be read or decrypted is an error.
+pub fn read_entries(file_path: &Path) -> Result<Vec<Entry>, Error> {
+    match read_file(file_path) {
+        Err(ref e) if e.kind() == ErrorKind::NotFound => Ok(Vec::new()),
+        result => result.map_err(crypt::read_error)
+    }
+}
+
+/// The entries `-n` or `-d` pick out, read without loading the rest of the
+/// ledger where its storage allows: the last `n` entries, or those from the
+/// first dated on or after the date, as `filter_entries` picks them.
+/// Anything else is left to `filter_entries`.
+pub fn read_selected(file_path: &Path, submatches: &ArgMatches) -> Result<Vec<Entry>, ioError> {
+    let storage = storage::open(file_path);
+    let count = submatches.value_of("num").and_then(|n| usize::from_str(n).ok());
+    let selected = match (count, date_value(submatches, "date")) {
+        (Some(n), _) => storage.last(n)?,
+        (None, Ok(Some(date))) => storage.since(&date)?,
+        _ => Vec::new()
+    };
+    if !selected.is_empty() {
+        return Ok(selected)
+    }
+    let entries = storage.load()?.entries;
+    Ok(filter_entries(&entries, submatches).to_vec())
+}
+
+pub fn filter_entries<'a>(entries: &'a [Entry], submatches: &ArgMatches) -> &'a [Entry] {
     if submatches.is_present("num") {
         submatches.value_of("num")
                   .ok_or(Error::InputError)
@@ -77,10 +155,10 @@ fn filter_entries<'a>(entries: &'a [Entry], submatches: &ArgMatches) -> &'a [Ent
                                        .checked_sub(n)
                                        .ok_or(Error::InputError))
     } else {
-        submatches.value_of("date")
-                  .ok_or(Error::InputError)
+        date_value(submatches, "date")
+                  .and_then(|date| date.ok_or(Error::InputError))
                   .and_then(|date_string|
-                      time::strptime(date_string, "%Y-%m-%d").map_err(|_| Error::InputError)
+                      time::strptime(&date_string, "%Y-%m-%d").map_err(|_| Error::InputError)
                   )
                   .and_then(|date|
                       entries.iter().position(|e| e.date() >= date).ok_or(Error::InputError)
@@ -90,25 +168,71 @@ fn filter_entries<'a>(entries: &'a [Entry], submatches: &ArgMatches) -> &'a [Ent
     .unwrap_or(entries)
 }
 
-fn delta_by_line<'a>(entries: &'a [Entry]) -> Vec<Delta<'a>> {
+pub fn delta_by_line<'a>(entries: &'a [Entry]) -> Vec<Delta<'a>> {
     entries.windows(2)
            .map(|es| Delta::new(&es[0], &es[1]))
            .collect::<Vec<Delta<'a>>>()
 }
 
-fn aggregate_delta<'a>(entries: &'a [Entry]) -> Delta<'a> {
-    Delta::new(&entries[0], &entries[entries.len() - 1])
+/// Splits a step into the part accounted for by recorded transactions in
+/// the interval and the unexplained residual.
+#[derive(PartialEq, Debug)]
+pub struct Explanation {
+    pub count: usize,
+    pub explained: f64,
+    pub unexplained: f64
+}
+
+impl fmt::Display for Explanation {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "explained {} by {} transaction{} | unexplained {}", canonical(self.explained),
+                                                                        self.count,
+                                                                        if self.count == 1 { "" } else { "s" },
+                                                                        canonical(self.unexplained))
+    }
+}
+
+pub fn explain(delta: &Delta, transactions: &[Transaction]) -> Explanation {
+    let within = between(transactions, &delta.start.date_string, &delta.end.date_string);
+    let explained = within.iter().map(|t| t.amount()).sum::<f64>();
+    Explanation { count: within.len(), explained, unexplained: delta.delta() - explained }
+}
+
+/// The `yyyy-mm` period a ledger date falls in.
+pub fn month_of(date_string: &str) -> &str {
+    date_string.get(..7).unwrap_or(date_string)
+}
+
+/// Keeps the steps that end on an entry carrying `tag`.
+fn tagged<'a>(deltas: Vec<Delta<'a>>, tag: Option<&str>) -> Vec<Delta<'a>> {
+    match tag {
+        Some(tag) => deltas.into_iter().filter(|d| d.end.has_tag(tag)).collect(),
+        None => deltas
+    }
+}
+
+fn aggregate_delta<'a>(entries: &'a [Entry]) -> Option<Delta<'a>> {
+    match (entries.first(), entries.last()) {
+        (Some(first), Some(last)) => Some(Delta::new(first, last)),
+        _ => None
+    }
 }
 
 #[cfg(test)]
 mod test {
+    use std::env;
+    use std::fs;
     use std::path::Path;
     use clap::{ Arg, App };
     use base::Entry;
+    use txn::Transaction;
     use super::{ Delta,
                  read_file,
+                 read_selected,
                  filter_entries,
                  delta_by_line,
+                 tagged,
+                 explain,
                  aggregate_delta
                };
 
@@ -181,6 +305,24 @@ mod test {
         assert_eq!(filtered_2, entries.split_at(2).1);
     }
 
+    #[test]
+    fn reads_the_same_entries_after_a_date_as_it_filters() {
+        let path = env::temp_dir().join(format!("budget_show_selected_{}", std::process::id()));
+        fs::write(&path, "2016-09-01|1000\n2016-11-01|1100\n2016-10-01|1200\n2016-12-01|1300\n").unwrap();
+        let matches = App::new("test")
+                          .arg(Arg::with_name("date")
+                                   .short("d")
+                                   .takes_value(true))
+                          .get_matches_from(vec!["test", "-d", "2016-10-15"]);
+
+        let entries = read_file(&path).unwrap();
+        let selected = read_selected(&path, &matches).unwrap();
+
+        assert_eq!(selected, filter_entries(&entries, &matches));
+        assert_eq!(selected[1], Entry::new("2016-10-01", "1200"));
+        fs::remove_file(path).unwrap();
+    }
+
     #[test]
     fn delta_calculates_difference_between_entries() {
         let entry_1 = Entry::new("2016-10-01", "1200");
@@ -201,6 +343,15 @@ mod test {
                    "2016-01-01 -> 2016-02-01: 1000.00 -> 2000.00 | 1000");
     }
 
+    #[test]
+    fn delta_marks_reconciled_entries() {
+        let e1 = Entry::new("2016-01-31", "1000.00").with_reconciled();
+        let e2 = Entry::new("2016-02-15", "1200.00");
+
+        assert_eq!(format!("{}", Delta::new(&e1, &e2)),
+                   "2016-01-31* -> 2016-02-15: 1000.00 -> 1200.00 | 200");
+    }
+
     #[test]
     fn returns_differences_for_each_entry() {
         let entries = vec![Entry::new("2016-09-01", "1000"),
@@ -226,6 +377,53 @@ mod test {
 
         let agg = aggregate_delta(&entries);
 
-        assert_eq!(agg, Delta::new(&entries[0], &entries[3]));
+        assert_eq!(agg, Some(Delta::new(&entries[0], &entries[3])));
+    }
+
+    #[test]
+    fn has_no_aggregate_delta_without_entries() {
+        assert_eq!(aggregate_delta(&[]), None);
+    }
+
+    #[test]
+    fn delta_displays_note_and_tags_of_end_entry() {
+        let e1 = Entry::new("2016-02-01", "1500.00");
+        let e2 = Entry::new("2016-03-05", "1250.00").with_note("car repair")
+                                                      .with_tags(&["car", "auto"]);
+
+        assert_eq!(format!("{}", Delta::new(&e1, &e2)),
+                   "2016-02-01 -> 2016-03-05: 1500.00 -> 1250.00 | -250 | car repair #car #auto");
+    }
+
+    #[test]
+    fn filters_differences_by_tag() {
+        let entries = vec![Entry::new("2016-09-01", "1000"),
+                           Entry::new("2016-10-01", "1200").with_tags(&["bonus"]),
+                           Entry::new("2016-11-01", "1100").with_tags(&["car"]),
+                           Entry::new("2016-12-01", "1300").with_tags(&["Car", "gift"])
+                          ];
+
+        let differences = tagged(delta_by_line(&entries), Some("car")).iter()
+                                                                      .map(|d| d.delta())
+                                                                      .collect::<Vec<f64>>();
+
+        assert_eq!(differences, vec![-100.0, 200.0]);
+        assert_eq!(tagged(delta_by_line(&entries), None).len(), 3);
+    }
+
+    #[test]
+    fn explains_delta_with_transactions() {
+        let e1 = Entry::new("2016-02-01", "1500.00");
+        let e2 = Entry::new("2016-03-05", "1250.00");
+        let transactions = vec![Transaction::new("2016-02-01", "-500.00"),
+                                Transaction::new("2016-02-10", "-120.00"),
+                                Transaction::new("2016-03-01", "-80.50"),
+                                Transaction::new("2016-03-06", "-15.00")
+                               ];
+
+        let explanation = explain(&Delta::new(&e1, &e2), &transactions);
+
+        assert_eq!(format!("{}", explanation),
+                   "explained -200.50 by 2 transactions | unexplained -49.50");
     }
 }
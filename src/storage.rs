@@ -0,0 +1,787 @@
+extern crate clap;
+
+use std::env;
+use std::fmt;
+use std::fs::{ self, File };
+use std::io::{ self, BufRead, BufReader, ErrorKind, Read, Seek, SeekFrom };
+use std::path::{ Path, PathBuf };
+use std::str;
+use clap::ArgMatches;
+use rusqlite::{ self, Connection, OpenFlags, Row, ToSql };
+
+use base::{ Entry, Error };
+use config::{ Config, default_config_path };
+use crypt;
+use index::{ Index, index_path, seek_date };
+use txn::{ Transaction, is_transaction_line };
+use watch::stamp;
+
+const SQLITE_EXTENSIONS: [&str; 3] = ["db", "sqlite", "sqlite3"];
+
+/// Text ledgers smaller than this are read in one pass rather than indexed.
+const INDEX_THRESHOLD: u64 = 1 << 20;
+
+/// How much of a text ledger is read at a time when reading from the end.
+const CHUNK: u64 = 1 << 16;
+
+const SCHEMA: &str = "CREATE TABLE IF NOT EXISTS entries (
+                          id INTEGER PRIMARY KEY,
+                          date TEXT NOT NULL,
+                          amount TEXT NOT NULL,
+                          note TEXT,
+                          tags TEXT NOT NULL DEFAULT '',
+                          reconciled INTEGER NOT NULL DEFAULT 0
+                      );
+                      CREATE INDEX IF NOT EXISTS entries_date ON entries (date);
+                      CREATE TABLE IF NOT EXISTS transactions (
+                          id INTEGER PRIMARY KEY,
+                          date TEXT NOT NULL,
+                          amount TEXT NOT NULL,
+                          payee TEXT,
+                          category TEXT,
+                          recurring TEXT
+                      );";
+
+/// A ledger line: a balance entry or a transaction.
+#[derive(PartialEq, Eq, Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Line {
+    Entry(Entry),
+    Transaction(Transaction)
+}
+
+impl Line {
+    pub fn parse(line: &str) -> Self {
+        match Transaction::from_line(line) {
+            Some(transaction) => Line::Transaction(transaction),
+            None => Line::Entry(Entry::from_line(line))
+        }
+    }
+
+    pub fn date(&self) -> &str {
+        match *self {
+            Line::Entry(ref entry) => &entry.date_string,
+            Line::Transaction(ref transaction) => &transaction.date_string
+        }
+    }
+
+    pub fn same_kind(&self, other: &Line) -> bool {
+        matches!((self, other), (Line::Entry(_), Line::Entry(_)) | (Line::Transaction(_), Line::Transaction(_)))
+    }
+}
+
+impl From<Entry> for Line {
+    fn from(entry: Entry) -> Self {
+        Line::Entry(entry)
+    }
+}
+
+impl From<Transaction> for Line {
+    fn from(transaction: Transaction) -> Self {
+        Line::Transaction(transaction)
+    }
+}
+
+impl fmt::Display for Line {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let line = match *self {
+            Line::Entry(ref entry) => entry.to_string(),
+            Line::Transaction(ref transaction) => transaction.to_string()
+        };
+        write!(f, "{}", line.trim_end())
+    }
+}
+
+/// Everything in a ledger.
+#[derive(PartialEq, Eq, Debug, Default)]
+pub struct Contents {
+    pub entries: Vec<Entry>,
+    pub transactions: Vec<Transaction>
+}
+
+impl Contents {
+    /// Entries, then transactions, as ledger lines.
+    pub fn lines(&self) -> Vec<Line> {
+        self.entries.iter()
+                    .cloned()
+                    .map(Line::from)
+                    .chain(self.transactions.iter().cloned().map(Line::from))
+                    .collect()
+    }
+}
+
+/// Where a ledger is kept.
+pub trait Storage {
+    /// Every entry, in the order kept, and every transaction.
+    fn load(&self) -> io::Result<Contents>;
+
+    /// Adds a line at the end of the ledger.
+    fn append(&mut self, line: &Line) -> io::Result<()>;
+
+    /// Adds a line where it belongs, an entry before any dated later. A
+    /// backend that reads entries back in date order can just append.
+    fn insert(&mut self, line: &Line) -> io::Result<()> {
+        self.append(line)
+    }
+
+    /// Replaces the first line equal to `old`; false if there is none.
+    fn update(&mut self, old: &Line, new: &Line) -> io::Result<bool>;
+
+    /// Removes the first line equal to `line`; false if there is none.
+    fn delete(&mut self, line: &Line) -> io::Result<bool>;
+
+    /// Entries dated within `from` and `to`, both inclusive when given.
+    fn query(&self, from: Option<&str>, to: Option<&str>) -> io::Result<Vec<Entry>>;
+
+    /// Every entry from the first dated on or after `from`, in the order
+    /// kept, as `show -d` picks them; an earlier date after it is kept.
+    fn since(&self, from: &str) -> io::Result<Vec<Entry>>;
+
+    /// The last `n` entries, in the order kept.
+    fn last(&self, n: usize) -> io::Result<Vec<Entry>>;
+
+    /// Every transaction.
+    fn transactions(&self) -> io::Result<Vec<Transaction>>;
+
+    /// Replaces everything in the ledger with `contents`.
+    fn save(&mut self, contents: &Contents) -> io::Result<()>;
+}
+
+fn within(entry: &Entry, from: Option<&str>, to: Option<&str>) -> bool {
+    from.is_none_or(|from| entry.date_string.as_str() >= from) && to.is_none_or(|to| entry.date_string.as_str() <= to)
+}
+
+/// The `|`-delimited text file, encrypted or not.
+pub struct TextFile {
+    path: PathBuf,
+    indexed: bool
+}
+
+impl TextFile {
+    pub fn new(path: &Path) -> Self {
+        TextFile { path: path.to_path_buf(), indexed: false }
+    }
+
+    /// Keeps a `<ledger>.index` beside a large plain ledger.
+    pub fn with_index(mut self) -> Self {
+        self.indexed = true;
+        self
+    }
+
+    fn lines(&self) -> io::Result<Vec<String>> {
+        match crypt::read(&self.path) {
+            Ok(contents) => Ok(contents.lines().map(|l| l.to_string()).collect()),
+            Err(ref e) if e.kind() == ErrorKind::NotFound => Ok(Vec::new()),
+            Err(e) => Err(e)
+        }
+    }
+
+    fn write_lines(&self, lines: &[String]) -> io::Result<()> {
+        let contents = lines.iter().map(|l| format!("{}\n", l)).collect::<String>();
+        crypt::write(&self.path, &contents, crypt::is_encrypted(&self.path))
+    }
+
+    /// The file to read directly, unless it is encrypted and has to be
+    /// decrypted whole.
+    fn plain(&self) -> io::Result<Option<File>> {
+        let file = File::open(&self.path)?;
+        Ok(if crypt::is_encrypted(&self.path) { None } else { Some(file) })
+    }
+
+    /// The index of a plain ledger large enough to be worth one, when
+    /// asked for.
+    fn index(&self) -> io::Result<Option<Index>> {
+        if !self.indexed {
+            return Ok(None)
+        }
+        match self.plain()? {
+            Some(ref file) if file.metadata()?.len() >= INDEX_THRESHOLD => Index::current(&self.path).map(Some),
+            _ => Ok(None)
+        }
+    }
+
+    /// Passes each line from `offset` on to `visit`, skipping blank ones,
+    /// until it returns false. An encrypted ledger is read from the start.
+    fn stream<F>(&self, offset: u64, mut visit: F) -> io::Result<()>
+        where F: FnMut(&str) -> bool {
+        match self.plain()? {
+            Some(mut file) => {
+                file.seek(SeekFrom::Start(offset))?;
+                for line in BufReader::new(file).lines() {
+                    let line = line?;
+                    if !line.trim().is_empty() && !visit(&line) {
+                        break
+                    }
+                }
+            },
+            None => {
+                for line in crypt::read(&self.path)?.lines() {
+                    if !line.trim().is_empty() && !visit(line) {
+                        break
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Storage for TextFile {
+    fn load(&self) -> io::Result<Contents> {
+        let mut loaded = Contents::default();
+        self.stream(0, |line| {
+            match Line::parse(line) {
+                Line::Transaction(transaction) => loaded.transactions.push(transaction),
+                Line::Entry(entry) => loaded.entries.push(entry)
+            }
+            true
+        })?;
+        Ok(loaded)
+    }
+
+    fn append(&mut self, line: &Line) -> io::Result<()> {
+        // an index still describing the ledger is kept current rather than
+        // rebuilt, which relies on the line going at the end
+        let index = Index::saved(&self.path);
+        crypt::append(&self.path, &format!("{}\n", line), crypt::is_encrypted(&self.path))?;
+        if let (Some(mut index), Some(stamp)) = (index, stamp(&self.path)) {
+            index.add(&line.to_string());
+            index.stamp = stamp;
+            index.save(&self.path);
+        }
+        Ok(())
+    }
+
+    fn insert(&mut self, line: &Line) -> io::Result<()> {
+        let mut lines = self.lines()?;
+        let later = match *line {
+            Line::Entry(ref entry) => lines.iter().position(|l| {
+                !is_transaction_line(l) && Entry::from_line(l.as_str()).date_string > entry.date_string
+            }),
+            Line::Transaction(_) => None
+        };
+        match later {
+            Some(i) => {
+                lines.insert(i, line.to_string());
+                self.write_lines(&lines)
+            },
+            None => self.append(line)
+        }
+    }
+
+    fn update(&mut self, old: &Line, new: &Line) -> io::Result<bool> {
+        let mut lines = self.lines()?;
+        match lines.iter().position(|l| Line::parse(l) == *old) {
+            Some(i) => {
+                lines[i] = new.to_string();
+                self.write_lines(&lines).map(|_| true)
+            },
+            None => Ok(false)
+        }
+    }
+
+    fn delete(&mut self, line: &Line) -> io::Result<bool> {
+        let mut lines = self.lines()?;
+        match lines.iter().position(|l| Line::parse(l) == *line) {
+            Some(i) => {
+                lines.remove(i);
+                self.write_lines(&lines).map(|_| true)
+            },
+            None => Ok(false)
+        }
+    }
+
+    /// A ledger in date order is searched for `from` and read only up to
+    /// `to`; any other is read through.
+    fn query(&self, from: Option<&str>, to: Option<&str>) -> io::Result<Vec<Entry>> {
+        let sorted = self.index()?.is_some_and(|index| index.sorted);
+        let offset = match from {
+            Some(from) if sorted => seek_date(&self.path, from)?,
+            _ => 0
+        };
+        let mut entries = Vec::new();
+        self.stream(offset, |line| {
+            if is_transaction_line(line) {
+                return true
+            }
+            let entry = Entry::from_line(line);
+            if sorted && to.is_some_and(|to| entry.date_string.as_str() > to) {
+                return false
+            }
+            if within(&entry, from, to) {
+                entries.push(entry);
+            }
+            true
+        })?;
+        Ok(entries)
+    }
+
+    fn since(&self, from: &str) -> io::Result<Vec<Entry>> {
+        let sorted = self.index()?.is_some_and(|index| index.sorted);
+        let offset = if sorted { seek_date(&self.path, from)? } else { 0 };
+        let mut entries = Vec::new();
+        self.stream(offset, |line| {
+            if !is_transaction_line(line) {
+                let entry = Entry::from_line(line);
+                if !entries.is_empty() || entry.date_string.as_str() >= from {
+                    entries.push(entry);
+                }
+            }
+            true
+        })?;
+        Ok(entries)
+    }
+
+    /// Reads back from the end of a plain ledger a chunk at a time until
+    /// `n` entries are found.
+    fn last(&self, n: usize) -> io::Result<Vec<Entry>> {
+        let mut file = match self.plain()? {
+            Some(file) => file,
+            None => return self.load().map(|contents| {
+                let skip = contents.entries.len().saturating_sub(n);
+                contents.entries.into_iter().skip(skip).collect()
+            })
+        };
+        let mut end = file.metadata()?.len();
+        // the start of a line whose beginning is in an earlier chunk
+        let mut partial = Vec::new();
+        let mut entries = Vec::new();
+        while entries.len() < n && end > 0 {
+            let start = end.saturating_sub(CHUNK);
+            let mut chunk = vec![0; (end - start) as usize];
+            file.seek(SeekFrom::Start(start))?;
+            file.read_exact(&mut chunk)?;
+            chunk.append(&mut partial);
+            let first = match chunk.iter().position(|&b| b == b'\n') {
+                _ if start == 0 => 0,
+                Some(i) => i + 1,
+                None => chunk.len()
+            };
+            let whole = chunk.split_off(first);
+            partial = chunk;
+            for line in whole.split(|&b| b == b'\n').rev() {
+                let line = str::from_utf8(line).map_err(|e| io::Error::new(ErrorKind::InvalidData, e))?;
+                if line.trim().is_empty() || is_transaction_line(line) {
+                    continue
+                }
+                entries.push(Entry::from_line(line.trim_end_matches('\r')));
+                if entries.len() == n {
+                    break
+                }
+            }
+            end = start;
+        }
+        entries.reverse();
+        Ok(entries)
+    }
+
+    fn transactions(&self) -> io::Result<Vec<Transaction>> {
+        if self.index()?.is_some_and(|index| !index.transactions) {
+            return Ok(Vec::new())
+        }
+        let mut transactions = Vec::new();
+        self.stream(0, |line| {
+            transactions.extend(Transaction::from_line(line));
+            true
+        })?;
+        Ok(transactions)
+    }
+
+    fn save(&mut self, contents: &Contents) -> io::Result<()> {
+        self.write_lines(&contents.lines().iter().map(|l| l.to_string()).collect::<Vec<String>>())
+    }
+}
+
+/// An embedded SQLite database, with entries indexed by date.
+pub struct Sqlite {
+    path: PathBuf
+}
+
+fn sql_error(e: rusqlite::Error) -> io::Error {
+    io::Error::other(e.to_string())
+}
+
+fn entry_from_row(row: &Row) -> rusqlite::Result<Entry> {
+    let tags: String = row.get(3)?;
+    let mut entry = Entry::new(row.get::<_, String>(0)?, row.get::<_, String>(1)?);
+    entry.note = row.get(2)?;
+    entry.tags = tags.split(',').filter(|t| !t.is_empty()).map(|t| t.to_string()).collect();
+    entry.reconciled = row.get(4)?;
+    Ok(entry)
+}
+
+fn transaction_from_row(row: &Row) -> rusqlite::Result<Transaction> {
+    let mut transaction = Transaction::new(row.get::<_, String>(0)?, row.get::<_, String>(1)?);
+    transaction.payee = row.get(2)?;
+    transaction.category = row.get(3)?;
+    transaction.recurring = row.get(4)?;
+    Ok(transaction)
+}
+
+const ENTRY_COLUMNS: &str = "date, amount, note, tags, reconciled";
+const TRANSACTION_COLUMNS: &str = "date, amount, payee, category, recurring";
+
+fn select_entries(connection: &Connection, condition: &str, values: &[&dyn ToSql]) -> rusqlite::Result<Vec<Entry>> {
+    connection.prepare(&format!("SELECT {} FROM entries {} ORDER BY date, id", ENTRY_COLUMNS, condition))?
+              .query_map(values, entry_from_row)?
+              .collect()
+}
+
+fn select_transactions(connection: &Connection) -> rusqlite::Result<Vec<Transaction>> {
+    connection.prepare(&format!("SELECT {} FROM transactions ORDER BY id", TRANSACTION_COLUMNS))?
+              .query_map([], transaction_from_row)?
+              .collect()
+}
+
+/// The table, and a condition with its values, picking out the rows
+/// equal to `line`.
+fn matching(line: &Line) -> (&'static str, &'static str, Vec<Box<dyn ToSql>>) {
+    match *line {
+        Line::Entry(ref e) => ("entries",
+                               "date = ? AND amount = ? AND note IS ? AND tags = ? AND reconciled = ?",
+                               vec![Box::new(e.date_string.clone()), Box::new(e.amount_string.clone()),
+                                    Box::new(e.note.clone()), Box::new(e.tags.join(",")), Box::new(e.reconciled)]),
+        Line::Transaction(ref t) => ("transactions",
+                                     "date = ? AND amount = ? AND payee IS ? AND category IS ? AND recurring IS ?",
+                                     vec![Box::new(t.date_string.clone()), Box::new(t.amount_string.clone()),
+                                          Box::new(t.payee.clone()), Box::new(t.category.clone()),
+                                          Box::new(t.recurring.clone())])
+    }
+}
+
+fn insert(connection: &Connection, line: &Line) -> rusqlite::Result<usize> {
+    match *line {
+        Line::Entry(ref e) => connection.execute(&format!("INSERT INTO entries ({}) VALUES (?, ?, ?, ?, ?)", ENTRY_COLUMNS),
+                                                 rusqlite::params![e.date_string, e.amount_string, e.note,
+                                                                   e.tags.join(","), e.reconciled]),
+        Line::Transaction(ref t) => connection.execute(&format!("INSERT INTO transactions ({}) VALUES (?, ?, ?, ?, ?)",
+                                                                TRANSACTION_COLUMNS),
+                                                       rusqlite::params![t.date_string, t.amount_string, t.payee,
+                                                                         t.category, t.recurring])
+    }
+}
+
+impl Sqlite {
+    pub fn new(path: &Path) -> Self {
+        Sqlite { path: path.to_path_buf() }
+    }
+
+    /// Reading a database that does not exist is `NotFound`, as it is for a
+    /// text file, rather than creating an empty one.
+    fn reader(&self) -> io::Result<Connection> {
+        if !self.path.exists() {
+            return Err(io::Error::new(ErrorKind::NotFound, format!("{} does not exist", self.path.display())))
+        }
+        Connection::open_with_flags(&self.path, OpenFlags::SQLITE_OPEN_READ_ONLY).map_err(sql_error)
+    }
+
+    fn writer(&self) -> io::Result<Connection> {
+        let connection = Connection::open(&self.path).map_err(sql_error)?;
+        connection.execute_batch(SCHEMA).map_err(sql_error)?;
+        Ok(connection)
+    }
+
+    fn change(&self, line: &Line, replacement: Option<&Line>) -> io::Result<bool> {
+        let mut connection = self.writer()?;
+        let transaction = connection.transaction().map_err(sql_error)?;
+        let (table, condition, values) = matching(line);
+        let values = values.iter().map(|v| v.as_ref()).collect::<Vec<&dyn ToSql>>();
+        let changed = transaction.execute(&format!("DELETE FROM {} WHERE id = \
+                                                    (SELECT id FROM {} WHERE {} ORDER BY id LIMIT 1)",
+                                                   table, table, condition),
+                                          &values[..])
+                                 .map_err(sql_error)?;
+        if changed == 0 {
+            return Ok(false)
+        }
+        if let Some(new) = replacement {
+            insert(&transaction, new).map_err(sql_error)?;
+        }
+        transaction.commit().map_err(sql_error).map(|_| true)
+    }
+}
+
+impl Storage for Sqlite {
+    fn load(&self) -> io::Result<Contents> {
+        let connection = self.reader()?;
+        let entries = select_entries(&connection, "", &[]).map_err(sql_error)?;
+        let transactions = select_transactions(&connection).map_err(sql_error)?;
+        Ok(Contents { entries, transactions })
+    }
+
+    fn append(&mut self, line: &Line) -> io::Result<()> {
+        insert(&self.writer()?, line).map(|_| ()).map_err(sql_error)
+    }
+
+    fn update(&mut self, old: &Line, new: &Line) -> io::Result<bool> {
+        self.change(old, Some(new))
+    }
+
+    fn delete(&mut self, line: &Line) -> io::Result<bool> {
+        self.change(line, None)
+    }
+
+    fn query(&self, from: Option<&str>, to: Option<&str>) -> io::Result<Vec<Entry>> {
+        let connection = self.reader()?;
+        select_entries(&connection, "WHERE (?1 IS NULL OR date >= ?1) AND (?2 IS NULL OR date <= ?2)", &[&from, &to])
+            .map_err(sql_error)
+    }
+
+    /// Entries are read back in date order, so those from the date on are
+    /// all of the rest.
+    fn since(&self, from: &str) -> io::Result<Vec<Entry>> {
+        self.query(Some(from), None)
+    }
+
+    fn last(&self, n: usize) -> io::Result<Vec<Entry>> {
+        let connection = self.reader()?;
+        select_entries(&connection, "WHERE id IN (SELECT id FROM entries ORDER BY date DESC, id DESC LIMIT ?1)",
+                       &[&(n as i64)])
+            .map_err(sql_error)
+    }
+
+    fn transactions(&self) -> io::Result<Vec<Transaction>> {
+        select_transactions(&self.reader()?).map_err(sql_error)
+    }
+
+    fn save(&mut self, contents: &Contents) -> io::Result<()> {
+        let mut connection = self.writer()?;
+        let transaction = connection.transaction().map_err(sql_error)?;
+        transaction.execute_batch("DELETE FROM entries; DELETE FROM transactions;").map_err(sql_error)?;
+        for line in contents.lines() {
+            insert(&transaction, &line).map_err(sql_error)?;
+        }
+        transaction.commit().map_err(sql_error)
+    }
+}
+
+/// SQLite for a `.db`, `.sqlite` or `.sqlite3` file, or when the ledger's
+/// config says `backend = sqlite` under `[storage]`; text otherwise.
+/// Whether the `[storage]` section of the ledger's config sets `key` to
+/// `value`.
+fn configured(path: &Path, key: &str, value: &str) -> bool {
+    Config::read(&default_config_path(path))
+           .map(|config| config.section("storage")
+                               .iter()
+                               .any(|(k, v)| k == key && v == value))
+           .unwrap_or(false)
+}
+
+pub fn is_sqlite(path: &Path) -> bool {
+    let extension = path.extension().and_then(|e| e.to_str()).unwrap_or("");
+    SQLITE_EXTENSIONS.contains(&extension) || configured(path, "backend", "sqlite")
+}
+
+pub fn open(path: &Path) -> Box<dyn Storage> {
+    if is_sqlite(path) {
+        Box::new(Sqlite::new(path))
+    } else if configured(path, "index", "true") {
+        Box::new(TextFile::new(path).with_index())
+    } else {
+        Box::new(TextFile::new(path))
+    }
+}
+
+/// The entries in an earlier version of the ledger at `path`, given the
+/// bytes of that version as git has them.
+pub fn entries_in(path: &Path, bytes: Vec<u8>) -> io::Result<Vec<Entry>> {
+    if !is_sqlite(path) {
+        let contents = crypt::decode(bytes, path)?;
+        return Ok(contents.lines()
+                          .filter(|l| !l.trim().is_empty())
+                          .filter_map(|l| match Line::parse(l) {
+                              Line::Entry(entry) => Some(entry),
+                              Line::Transaction(_) => None
+                          })
+                          .collect())
+    }
+    let copy = env::temp_dir().join(format!("budget_version_{}.db", std::process::id()));
+    fs::write(&copy, bytes)?;
+    let loaded = Sqlite::new(&copy).load();
+    let _ = fs::remove_file(&copy);
+    loaded.map(|contents| contents.entries)
+}
+
+pub fn run_convert(data_path: &Path, matches: &ArgMatches) -> Result<bool, Error> {
+    let submatches = matches.subcommand_matches("convert").ok_or(Error::InputError)?;
+    let target = Path::new(submatches.value_of("target").ok_or(Error::InputError)?);
+    if fs::metadata(target).map(|m| m.len() > 0).unwrap_or(false) {
+        println!("{} already exists; convert only writes new ledgers", target.display());
+        return Err(Error::InputError)
+    }
+    let contents = open(data_path).load().map_err(crypt::read_error)?;
+    // an index left from an earlier ledger by this name no longer applies
+    let _ = fs::remove_file(index_path(target));
+    let mut converted: Box<dyn Storage> = match submatches.value_of("backend") {
+        Some("sqlite") => Box::new(Sqlite::new(target)),
+        Some(_) => Box::new(TextFile::new(target)),
+        None => open(target)
+    };
+    converted.save(&contents).map_err(|e| {
+        println!("Could not write {}: {}", target.display(), e);
+        Error::WriteError
+    })?;
+    println!("Converted {} entries and {} transactions to {}", contents.entries.len(),
+                                                             contents.transactions.len(),
+                                                             target.display());
+    Ok(true)
+}
+
+#[cfg(test)]
+mod test {
+    use std::env;
+    use std::fs;
+    use std::path::{ Path, PathBuf };
+    use std::time::{ Duration, Instant };
+    use base::Entry;
+    use index::{ Index, index_path };
+    use txn::Transaction;
+    use super::{ Contents, Line, Sqlite, Storage, TextFile };
+
+    fn scratch(name: &str, extension: &str) -> PathBuf {
+        let path = env::temp_dir().join(format!("budget_storage_{}_{}.{}", name, std::process::id(), extension));
+        let _ = fs::remove_file(&path);
+        path
+    }
+
+    fn contents() -> Contents {
+        Contents { entries: vec![Entry::new("2016-01-01", "1000.00"),
+                                 Entry::new("2016-02-01", "1200.00").with_note("bonus").with_tags(&["work"]),
+                                 Entry::new("2016-03-01", "900.00").with_reconciled()],
+                   transactions: vec![Transaction::new("2016-01-15", "-40.00").with_payee("Grocer")] }
+    }
+
+    /// The same sequence of operations gives the same ledger on either
+    /// backend.
+    fn exercise(storage: &mut dyn Storage) {
+        storage.save(&contents()).unwrap();
+        assert_eq!(storage.load().unwrap(), contents());
+
+        storage.append(&Line::Entry(Entry::new("2016-03-20", "950.00"))).unwrap();
+        storage.append(&Line::Transaction(Transaction::new("2016-02-10", "-15.00"))).unwrap();
+        assert!(storage.update(&Line::Entry(Entry::new("2016-03-01", "900.00").with_reconciled()),
+                               &Line::Entry(Entry::new("2016-03-01", "925.00"))).unwrap());
+        assert!(storage.delete(&Line::Entry(Entry::new("2016-01-01", "1000.00"))).unwrap());
+        assert!(!storage.delete(&Line::Entry(Entry::new("2016-01-01", "1000.00"))).unwrap());
+
+        let loaded = storage.load().unwrap();
+        assert_eq!(loaded.entries.iter().map(|e| e.to_string()).collect::<String>(),
+                   "2016-02-01|1200.00|note=bonus|tags=work\n2016-03-01|925.00\n2016-03-20|950.00\n");
+        assert_eq!(loaded.transactions.len(), 2);
+        assert_eq!(storage.query(Some("2016-02-01"), Some("2016-02-28")).unwrap(),
+                   vec![Entry::new("2016-02-01", "1200.00").with_note("bonus").with_tags(&["work"])]);
+        assert_eq!(storage.query(Some("2016-03-02"), None).unwrap(), vec![Entry::new("2016-03-20", "950.00")]);
+        assert_eq!(storage.since("2016-03-01").unwrap(), loaded.entries[1..].to_vec());
+        assert_eq!(storage.last(2).unwrap(), loaded.entries[1..].to_vec());
+        assert_eq!(storage.last(10).unwrap(), loaded.entries);
+        assert_eq!(storage.transactions().unwrap(), loaded.transactions);
+    }
+
+    /// A ledger of `lines` entries in date order, 28 days to a month, with a
+    /// transaction every hundred lines.
+    fn generate(path: &Path, lines: usize) {
+        let mut ledger = String::new();
+        for i in 0..lines {
+            let date = format!("{}-{:02}-{:02}", 1000 + i / 336, i / 28 % 12 + 1, i % 28 + 1);
+            if i % 100 == 99 {
+                ledger.push_str(&format!("txn|{}|-{}.00\n", date, i % 50));
+            } else {
+                ledger.push_str(&format!("{}|{}.00\n", date, i % 5000));
+            }
+        }
+        fs::write(path, ledger).unwrap();
+    }
+
+    #[test]
+    fn large_text_ledger_is_searched_by_index() {
+        let path = scratch("large", "txt");
+        generate(&path, 80_000);
+        let mut storage = TextFile::new(&path).with_index();
+        let loaded = storage.load().unwrap();
+
+        let range = storage.query(Some("1100-03-05"), Some("1101-01-01")).unwrap();
+        assert_eq!(range, loaded.entries.iter()
+                                        .filter(|e| e.date_string.as_str() >= "1100-03-05" &&
+                                                    e.date_string.as_str() <= "1101-01-01")
+                                        .cloned()
+                                        .collect::<Vec<Entry>>());
+        assert_eq!(range.len(), 274);
+        assert!(Index::saved(&path).unwrap().sorted);
+        let first = loaded.entries.iter().position(|e| e.date_string.as_str() >= "1100-03-05").unwrap();
+        assert_eq!(storage.since("1100-03-05").unwrap(), loaded.entries[first..].to_vec());
+        assert_eq!(storage.last(300).unwrap(), loaded.entries[loaded.entries.len() - 300..].to_vec());
+        assert_eq!(storage.transactions().unwrap(), loaded.transactions);
+
+        storage.append(&Line::Entry(Entry::new("3000-01-01", "5.00"))).unwrap();
+        assert_eq!(Index::saved(&path).unwrap().last, Some("3000-01-01".to_string()));
+        storage.append(&Line::Entry(Entry::new("1100-03-05", "5.00"))).unwrap();
+        assert!(!Index::saved(&path).unwrap().sorted);
+        assert_eq!(storage.query(Some("1100-03-05"), Some("1100-03-05")).unwrap(),
+                   vec![Entry::new("1100-03-05", "3660.00"), Entry::new("1100-03-05", "5.00")]);
+        assert_eq!(storage.since("2999-01-01").unwrap(),
+                   vec![Entry::new("3000-01-01", "5.00"), Entry::new("1100-03-05", "5.00")]);
+        fs::remove_file(index_path(&path)).unwrap();
+        fs::remove_file(path).unwrap();
+    }
+
+    /// Prints how long `show -n 2` and `show -d` style reads take as the
+    /// ledger grows to a million lines, against loading it whole. Run with
+    /// `cargo test --release -- --ignored --nocapture read_latency`.
+    #[test]
+    #[ignore]
+    fn read_latency_is_flat() {
+        fn time<F: FnMut()>(mut read: F) -> Duration {
+            let mut runs = (0..5).map(|_| {
+                let start = Instant::now();
+                read();
+                start.elapsed()
+            }).collect::<Vec<Duration>>();
+            runs.sort();
+            runs[2]
+        }
+
+        let mut latencies = Vec::new();
+        println!("{:>9}  {:>10}  {:>10}  {:>10}", "lines", "last 2", "from date", "load");
+        for &lines in &[100_000, 300_000, 1_000_000] {
+            let path = scratch(&format!("latency_{}", lines), "txt");
+            generate(&path, lines);
+            let storage = TextFile::new(&path).with_index();
+            let recent = format!("{}-01-01", 1000 + lines / 336);
+            storage.since(&recent).unwrap();
+
+            let last = time(|| assert_eq!(storage.last(2).unwrap().len(), 2));
+            let from = time(|| assert!(!storage.since(&recent).unwrap().is_empty()));
+            let load = time(|| assert!(!storage.load().unwrap().entries.is_empty()));
+            println!("{:>9}  {:>10?}  {:>10?}  {:>10?}", lines, last, from, load);
+            latencies.push((last, from));
+            fs::remove_file(index_path(&path)).unwrap();
+            fs::remove_file(path).unwrap();
+        }
+
+        let (smallest, largest) = (latencies[0], latencies[latencies.len() - 1]);
+        assert!(largest.0 < smallest.0 * 10 + Duration::from_millis(1));
+        assert!(largest.1 < smallest.1 * 10 + Duration::from_millis(1));
+    }
+
+    #[test]
+    fn text_file_implements_storage() {
+        let path = scratch("text", "txt");
+        exercise(&mut TextFile::new(&path));
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn sqlite_implements_storage() {
+        let path = scratch("sqlite", "db");
+        exercise(&mut Sqlite::new(&path));
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn missing_sqlite_ledger_is_not_created_by_reading() {
+        let path = scratch("missing", "db");
+
+        assert_eq!(Sqlite::new(&path).load().unwrap_err().kind(), std::io::ErrorKind::NotFound);
+        assert!(!path.exists());
+    }
+}
@@ -0,0 +1,553 @@
+extern crate clap;
+extern crate crossterm;
+
+use std::io::{ self, Write };
+use std::path::{ Path, PathBuf };
+use atty::{ self, Stream };
+use clap::ArgMatches;
+use crossterm::{ ExecutableCommand, QueueableCommand };
+use crossterm::cursor;
+use crossterm::event::{ self, Event, KeyCode, KeyEventKind, KeyModifiers };
+use crossterm::terminal::{ self, ClearType };
+
+use add::{ invalid_message, normalized_amount, write_entries };
+use amount::canonical;
+use base::{ Entry, Error, Validation };
+use chart::line_chart;
+use date::{ self, normalize_date, invalid_date_message };
+use journal;
+use show::read_entries;
+use watch::{ Stamp, stamp };
+
+const LIST_WIDTH: usize = 42;
+const MIN_CHART_WIDTH: usize = 30;
+const PAGE: usize = 10;
+const HELP: &str = "j/k move  a add  e edit  d delete  u undo  f filter  s save  q quit";
+
+/// Keys the browser responds to, apart from how the terminal reports them.
+#[derive(PartialEq, Eq, Debug, Clone, Copy)]
+pub enum Key {
+    Char(char),
+    Up,
+    Down,
+    PageUp,
+    PageDown,
+    Home,
+    End,
+    Enter,
+    Esc,
+    Backspace
+}
+
+#[derive(PartialEq, Eq, Debug, Clone, Copy)]
+enum Prompt {
+    Add,
+    Edit,
+    Filter
+}
+
+impl Prompt {
+    fn label(&self) -> &'static str {
+        match *self {
+            Prompt::Add => "add (date amount)",
+            Prompt::Edit => "edit (date amount)",
+            Prompt::Filter => "filter (from..to, blank for all)"
+        }
+    }
+}
+
+#[derive(PartialEq, Eq, Debug)]
+pub enum Outcome {
+    Continue,
+    Quit
+}
+
+/// The state of the browser. Changes are made to a working copy of the
+/// entries, each one recorded so it can be undone, until `s` writes them.
+/// The working copy replaces the whole ledger, so it is only written while
+/// the ledger still has the stamp it had when read.
+pub struct App {
+    path: PathBuf,
+    stamp: Option<Stamp>,
+    entries: Vec<Entry>,
+    undo: Vec<Vec<Entry>>,
+    from: Option<String>,
+    to: Option<String>,
+    selected: usize,
+    scroll: usize,
+    input: Option<(Prompt, String)>,
+    status: String,
+    quitting: bool
+}
+
+/// Reads `Sep 1 2016 1250.00` as a date followed by an amount, the way
+/// `add` reads its two arguments.
+fn parse_entry(input: &str) -> Result<Entry, String> {
+    let (date_input, amount) = input.trim()
+                                    .rsplit_once(char::is_whitespace)
+                                    .ok_or("Enter a date and an amount, like 2016-03-01 1250.00")?;
+    let date = normalize_date(date_input, &date::today()).map_err(|e| invalid_date_message(date_input, &e))?;
+    let entry = Entry::new(date, normalized_amount(amount)?);
+    match entry.validate() {
+        Validation::Valid => Ok(entry),
+        invalid => Err(invalid_message(&entry, &invalid))
+    }
+}
+
+/// Reads `from..to`, where either side may be left out; a single date is
+/// taken as `from`.
+fn parse_filter(input: &str) -> Result<(Option<String>, Option<String>), String> {
+    let (from, to) = input.split_once("..").unwrap_or((input, ""));
+    let date = |text: &str| match text.trim() {
+        "" => Ok(None),
+        text => normalize_date(text, &date::today()).map(Some).map_err(|e| invalid_date_message(text, &e))
+    };
+    Ok((date(from)?, date(to)?))
+}
+
+impl App {
+    pub fn new(path: &Path, entries: Vec<Entry>) -> Self {
+        App { path: path.to_path_buf(),
+              stamp: stamp(path),
+              entries,
+              undo: Vec::new(),
+              from: None,
+              to: None,
+              selected: 0,
+              scroll: 0,
+              input: None,
+              status: String::new(),
+              quitting: false
+            }
+    }
+
+    /// Reads the ledger, taking its stamp first so that a change made
+    /// while it is being read is not missed.
+    pub fn open(path: &Path) -> Result<Self, Error> {
+        let stamp = stamp(path);
+        let mut app = App::new(path, read_entries(path)?);
+        app.stamp = stamp;
+        Ok(app)
+    }
+
+    fn dirty(&self) -> bool {
+        !self.undo.is_empty()
+    }
+
+    /// Indexes of the entries within the date filter.
+    fn visible(&self) -> Vec<usize> {
+        self.entries.iter()
+                    .enumerate()
+                    .filter(|(_, e)| self.from.as_ref().is_none_or(|from| &e.date_string >= from) &&
+                                     self.to.as_ref().is_none_or(|to| &e.date_string <= to))
+                    .map(|(i, _)| i)
+                    .collect()
+    }
+
+    fn selected_index(&self) -> Option<usize> {
+        self.visible().get(self.selected).cloned()
+    }
+
+    fn clamp(&mut self) {
+        self.selected = self.selected.min(self.visible().len().saturating_sub(1));
+    }
+
+    fn change<F>(&mut self, change: F)
+        where F: FnOnce(&mut Vec<Entry>) {
+        self.undo.push(self.entries.clone());
+        change(&mut self.entries);
+        self.entries.sort_by(|a, b| a.date_string.cmp(&b.date_string));
+    }
+
+    /// Selects the entry on `date`, if the filter shows it.
+    fn select_date(&mut self, date: &str) {
+        let visible = self.visible();
+        match visible.iter().position(|&i| self.entries[i].date_string == date) {
+            Some(position) => self.selected = position,
+            None => self.status.push_str("; hidden by the filter")
+        }
+        self.clamp();
+    }
+
+    pub fn handle(&mut self, key: Key) -> Outcome {
+        if let Some((prompt, mut buffer)) = self.input.take() {
+            match key {
+                Key::Enter => self.submit(prompt, &buffer),
+                Key::Esc => self.status.clear(),
+                Key::Backspace => {
+                    buffer.pop();
+                    self.input = Some((prompt, buffer));
+                },
+                Key::Char(c) => {
+                    buffer.push(c);
+                    self.input = Some((prompt, buffer));
+                },
+                _ => self.input = Some((prompt, buffer))
+            }
+            return Outcome::Continue
+        }
+
+        let quitting = self.quitting;
+        self.quitting = false;
+        let last = self.visible().len().saturating_sub(1);
+        match key {
+            Key::Char('q') if self.dirty() && !quitting => {
+                self.status = "Unsaved changes: s saves, u undoes, q again quits without saving".to_string();
+                self.quitting = true;
+            },
+            Key::Char('q') => return Outcome::Quit,
+            Key::Up | Key::Char('k') => self.selected = self.selected.saturating_sub(1),
+            Key::Down | Key::Char('j') => self.selected = (self.selected + 1).min(last),
+            Key::PageUp => self.selected = self.selected.saturating_sub(PAGE),
+            Key::PageDown => self.selected = (self.selected + PAGE).min(last),
+            Key::Home | Key::Char('g') => self.selected = 0,
+            Key::End | Key::Char('G') => self.selected = last,
+            Key::Char('a') => self.input = Some((Prompt::Add, String::new())),
+            Key::Char('e') => if let Some(i) = self.selected_index() {
+                let entry = &self.entries[i];
+                self.input = Some((Prompt::Edit, format!("{} {}", entry.date_string, entry.amount_string)));
+            },
+            Key::Char('d') => if let Some(i) = self.selected_index() {
+                self.status = format!("Deleted {}", self.entries[i].date_string);
+                self.change(|entries| { entries.remove(i); });
+                self.clamp();
+            },
+            Key::Char('u') => match self.undo.pop() {
+                Some(entries) => {
+                    self.entries = entries;
+                    self.status = "Undid the last change".to_string();
+                    self.clamp();
+                },
+                None => self.status = "Nothing to undo".to_string()
+            },
+            Key::Char('f') => {
+                let current = format!("{}..{}", self.from.as_deref().unwrap_or(""), self.to.as_deref().unwrap_or(""));
+                self.input = Some((Prompt::Filter, if current == ".." { String::new() } else { current }));
+            },
+            Key::Char('s') => self.save(),
+            _ => {}
+        }
+        Outcome::Continue
+    }
+
+    fn submit(&mut self, prompt: Prompt, buffer: &str) {
+        match prompt {
+            Prompt::Filter => match parse_filter(buffer) {
+                Ok((from, to)) => {
+                    self.from = from;
+                    self.to = to;
+                    self.status = format!("Showing {} entries", self.visible().len());
+                    self.selected = 0;
+                },
+                Err(message) => self.status = message
+            },
+            Prompt::Add | Prompt::Edit => match parse_entry(buffer) {
+                Ok(entry) => {
+                    let date = entry.date_string.clone();
+                    let editing = if prompt == Prompt::Edit { self.selected_index() } else { None };
+                    match editing {
+                        Some(i) => {
+                            self.status = format!("Changed {}", self.entries[i].date_string);
+                            self.change(|entries| {
+                                let edited = &mut entries[i];
+                                if edited.date_string != entry.date_string || edited.amount_string != entry.amount_string {
+                                    edited.reconciled = false;
+                                }
+                                edited.date_string = entry.date_string;
+                                edited.amount_string = entry.amount_string;
+                            });
+                        },
+                        None => {
+                            self.status = format!("Added {}", date);
+                            self.change(|entries| entries.push(entry));
+                        }
+                    }
+                    self.select_date(&date);
+                },
+                Err(message) => {
+                    self.status = message;
+                    self.input = Some((prompt, buffer.to_string()));
+                }
+            }
+        }
+    }
+
+    fn save(&mut self) {
+        if stamp(&self.path) != self.stamp {
+            self.status = format!("{} changed since it was opened; quit and reopen it to keep both",
+                                  self.path.display());
+            return
+        }
+        match journal::recorded(&self.path, "tui", || write_entries(&self.entries, &self.path)) {
+            Ok(_) => {
+                self.stamp = stamp(&self.path);
+                self.undo.clear();
+                self.status = format!("Saved {} entries to {}", self.entries.len(), self.path.display());
+            },
+            Err(_) => self.status = format!("Could not write {}", self.path.display())
+        }
+    }
+
+    /// The screen as `height` lines of at most `width` characters: the
+    /// entry list with each step's change on the left, a chart of the
+    /// shown entries on the right when there is room, then the status and
+    /// the prompt or key help.
+    pub fn render(&mut self, width: usize, height: usize) -> Vec<String> {
+        let list_height = height.saturating_sub(4).max(1);
+        if self.selected < self.scroll {
+            self.scroll = self.selected;
+        } else if self.selected >= self.scroll + list_height {
+            self.scroll = self.selected + 1 - list_height;
+        }
+
+        let visible = self.visible();
+        let mut left = vec![format!("  {:<11} {:>12} {:>12}", "date", "balance", "change")];
+        for (position, &i) in visible.iter().enumerate().skip(self.scroll).take(list_height) {
+            let entry = &self.entries[i];
+            let change = match position {
+                0 => String::new(),
+                _ => canonical(entry.amount() - self.entries[visible[position - 1]].amount())
+            };
+            left.push(format!("{}{:<10}{} {:>12} {:>12}", if position == self.selected { "> " } else { "  " },
+                                                         entry.date_string,
+                                                         if entry.reconciled { "*" } else { " " },
+                                                         entry.amount_string,
+                                                         change));
+        }
+
+        let chart_width = width.saturating_sub(LIST_WIDTH + 1);
+        let chart = if chart_width >= MIN_CHART_WIDTH && visible.len() > 1 && list_height >= 4 {
+            let shown = visible.iter().map(|&i| self.entries[i].clone()).collect::<Vec<Entry>>();
+            line_chart(&shown, chart_width, list_height - 1).lines().map(|l| l.to_string()).collect()
+        } else {
+            Vec::new()
+        };
+
+        let filter = match (&self.from, &self.to) {
+            (None, None) => String::new(),
+            (from, to) => format!(" | {}..{}", from.as_deref().unwrap_or(""), to.as_deref().unwrap_or(""))
+        };
+        let mut screen = vec![format!("budget {}{}{}", self.path.display(),
+                                                       if self.dirty() { " [modified]" } else { "" },
+                                                       filter)];
+        for row in 0..list_height + 1 {
+            let list = left.get(row).map_or("", |l| l.as_str());
+            screen.push(match chart.get(row) {
+                Some(chart_line) => format!("{:<width$} {}", list, chart_line, width = LIST_WIDTH),
+                None => list.to_string()
+            });
+        }
+        screen.push(self.status.clone());
+        screen.push(match self.input {
+            Some((prompt, ref buffer)) => format!("{}: {}_", prompt.label(), buffer),
+            None => HELP.to_string()
+        });
+        screen.iter()
+              .map(|line| line.chars().take(width).collect::<String>().trim_end().to_string())
+              .collect()
+    }
+}
+
+fn key_of(code: KeyCode) -> Option<Key> {
+    match code {
+        KeyCode::Char(c) => Some(Key::Char(c)),
+        KeyCode::Up => Some(Key::Up),
+        KeyCode::Down => Some(Key::Down),
+        KeyCode::PageUp => Some(Key::PageUp),
+        KeyCode::PageDown => Some(Key::PageDown),
+        KeyCode::Home => Some(Key::Home),
+        KeyCode::End => Some(Key::End),
+        KeyCode::Enter => Some(Key::Enter),
+        KeyCode::Esc => Some(Key::Esc),
+        KeyCode::Backspace => Some(Key::Backspace),
+        _ => None
+    }
+}
+
+fn draw<W: Write>(out: &mut W, lines: &[String]) -> io::Result<()> {
+    for (row, line) in lines.iter().enumerate() {
+        out.queue(cursor::MoveTo(0, row as u16))?
+           .queue(terminal::Clear(ClearType::UntilNewLine))?;
+        write!(out, "{}", line)?;
+    }
+    out.queue(terminal::Clear(ClearType::FromCursorDown))?;
+    out.flush()
+}
+
+fn event_loop<W: Write>(app: &mut App, out: &mut W) -> io::Result<()> {
+    loop {
+        let (width, height) = terminal::size()?;
+        draw(out, &app.render(width as usize, height as usize))?;
+        if let Event::Key(key) = event::read()? {
+            // Ctrl-C asks to quit like q, so unsaved changes still get a warning
+            let code = match key.code {
+                KeyCode::Char('c') if key.modifiers.contains(KeyModifiers::CONTROL) => KeyCode::Char('q'),
+                code => code
+            };
+            if key.kind == KeyEventKind::Press && key_of(code).map(|k| app.handle(k)) == Some(Outcome::Quit) {
+                return Ok(())
+            }
+        }
+    }
+}
+
+pub fn run_tui(data_path: &Path, _: &ArgMatches) -> Result<bool, Error> {
+    if !atty::is(Stream::Stdin) || !atty::is(Stream::Stdout) {
+        println!("tui needs a terminal");
+        return Err(Error::InputError)
+    }
+    let mut app = App::open(data_path)?;
+    let mut stdout = io::stdout();
+    terminal::enable_raw_mode().map_err(|_| Error::InputError)?;
+    let result = stdout.execute(terminal::EnterAlternateScreen)
+                       .and_then(|out| out.execute(cursor::Hide))
+                       .and_then(|out| event_loop(&mut app, out));
+    let _ = stdout.execute(cursor::Show).and_then(|out| out.execute(terminal::LeaveAlternateScreen));
+    let _ = terminal::disable_raw_mode();
+    result.map(|_| true).map_err(|_| Error::WriteError)
+}
+
+#[cfg(test)]
+mod test {
+    use std::env;
+    use std::fs::{ self, OpenOptions };
+    use std::io::Write;
+    use std::path::Path;
+    use base::Entry;
+    use journal::{ journal_path, read_journal };
+    use super::{ App, Key, Outcome };
+
+    fn app() -> App {
+        App::new(Path::new("ledger"),
+                 vec![Entry::new("2016-01-01", "1000.00"),
+                      Entry::new("2016-02-01", "1500.00").with_note("bonus").with_reconciled(),
+                      Entry::new("2016-03-01", "1250.00")])
+    }
+
+    fn type_in(app: &mut App, keys: &str) {
+        for c in keys.chars() {
+            app.handle(Key::Char(c));
+        }
+    }
+
+    fn dates(app: &App) -> Vec<&str> {
+        app.entries.iter().map(|e| e.date_string.as_str()).collect()
+    }
+
+    #[test]
+    fn adds_entries_in_date_order_and_undoes() {
+        let mut app = app();
+        type_in(&mut app, "a2016-01-15 $1,200.00");
+        app.handle(Key::Enter);
+
+        assert_eq!(dates(&app), vec!["2016-01-01", "2016-01-15", "2016-02-01", "2016-03-01"]);
+        assert_eq!(app.entries[1], Entry::new("2016-01-15", "1200.00"));
+        assert_eq!(app.selected, 1);
+
+        app.handle(Key::Char('u'));
+        assert_eq!(dates(&app), vec!["2016-01-01", "2016-02-01", "2016-03-01"]);
+        assert!(!app.dirty());
+    }
+
+    #[test]
+    fn keeps_invalid_input_for_correction() {
+        let mut app = app();
+        type_in(&mut app, "a2016-01-15 lots");
+        app.handle(Key::Enter);
+
+        assert_eq!(app.entries.len(), 3);
+        assert!(app.status.starts_with("Invalid Amount lots"));
+        for _ in 0..4 {
+            app.handle(Key::Backspace);
+        }
+        type_in(&mut app, "1200");
+        app.handle(Key::Enter);
+        assert_eq!(app.entries.len(), 4);
+    }
+
+    #[test]
+    fn edits_keep_the_note_but_clear_reconciled() {
+        let mut app = app();
+        app.handle(Key::Down);
+        app.handle(Key::Char('e'));
+        for _ in 0.."1500.00".len() {
+            app.handle(Key::Backspace);
+        }
+        type_in(&mut app, "1450");
+        app.handle(Key::Enter);
+
+        assert_eq!(app.entries[1], Entry::new("2016-02-01", "1450.00").with_note("bonus"));
+    }
+
+    #[test]
+    fn deletes_within_filter() {
+        let mut app = app();
+        type_in(&mut app, "f2016-02-01..");
+        app.handle(Key::Enter);
+        app.handle(Key::End);
+        app.handle(Key::Char('d'));
+
+        assert_eq!(dates(&app), vec!["2016-01-01", "2016-02-01"]);
+        assert_eq!(app.visible(), vec![1]);
+        assert_eq!(app.selected, 0);
+    }
+
+    #[test]
+    fn warns_before_quitting_with_unsaved_changes() {
+        let mut app = app();
+        assert_eq!(app.handle(Key::Char('q')), Outcome::Quit);
+
+        app.handle(Key::Char('d'));
+        assert_eq!(app.handle(Key::Char('q')), Outcome::Continue);
+        assert_eq!(app.handle(Key::Char('q')), Outcome::Quit);
+    }
+
+    #[test]
+    fn saves_through_the_ledger_writer() {
+        let path = env::temp_dir().join(format!("budget_tui_{}", std::process::id()));
+        fs::write(&path, "2016-01-01|1000.00\n2016-02-01|1500.00\ntxn|2016-01-15|-40.00\n").unwrap();
+        let mut app = App::new(&path, vec![Entry::new("2016-01-01", "1000.00"),
+                                           Entry::new("2016-02-01", "1500.00")]);
+        app.handle(Key::Char('d'));
+        app.handle(Key::Char('s'));
+
+        assert_eq!(fs::read_to_string(&path).unwrap(), "2016-02-01|1500.00\ntxn|2016-01-15|-40.00\n");
+        assert!(!app.dirty());
+        assert_eq!(read_journal(&path).unwrap()[0].operation, "tui");
+        fs::remove_file(journal_path(&path)).unwrap();
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn refuses_to_save_over_changes_made_elsewhere() {
+        let path = env::temp_dir().join(format!("budget_tui_elsewhere_{}", std::process::id()));
+        fs::write(&path, "2016-01-01|1000.00\n2016-02-01|1500.00\n").unwrap();
+        let mut app = App::open(&path).unwrap();
+        app.handle(Key::Char('d'));
+        let mut other = OpenOptions::new().append(true).open(&path).unwrap();
+        other.write_all(b"2016-03-01|1250.00\n").unwrap();
+
+        app.handle(Key::Char('s'));
+
+        assert!(app.status.contains("changed since it was opened"));
+        assert!(app.dirty());
+        assert_eq!(fs::read_to_string(&path).unwrap(), "2016-01-01|1000.00\n2016-02-01|1500.00\n2016-03-01|1250.00\n");
+        assert!(!journal_path(&path).exists());
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn renders_list_with_changes() {
+        let mut app = app();
+        app.handle(Key::Down);
+
+        assert_eq!(app.render(44, 7),
+                   vec!["budget ledger",
+                        "  date             balance       change",
+                        "  2016-01-01       1000.00",
+                        "> 2016-02-01*      1500.00       500.00",
+                        "  2016-03-01       1250.00      -250.00",
+                        "",
+                        "j/k move  a add  e edit  d delete  u undo  f"]);
+    }
+}
@@ -0,0 +1,200 @@
+extern crate clap;
+extern crate time;
+
+use std::fmt;
+use std::io::{ self, ErrorKind };
+use std::path::Path;
+use clap::ArgMatches;
+
+use add::invalid_message;
+use amount::normalize_amount;
+use base::{ Entry, Validation, Error, escape, split_fields };
+use date::{ self, normalize_date, invalid_date_message };
+use journal;
+use storage::{ self, Line };
+
+/// Marks transaction lines, which share the ledger file with balance entries.
+pub const PREFIX: &str = "txn|";
+
+/// A single movement of money: negative for spending, positive for income.
+#[derive(PartialEq, Eq, Debug, Clone, Serialize, Deserialize)]
+pub struct Transaction {
+    #[serde(rename = "date")]
+    pub date_string: String,
+    #[serde(rename = "amount")]
+    pub amount_string: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub payee: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub category: Option<String>,
+    /// Name of the recurring item that generated the transaction.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub recurring: Option<String>
+}
+
+impl Transaction {
+    pub fn new<S>(date_string: S, amount_string: S) -> Self
+        where S: Into<String> {
+        Transaction { date_string: date_string.into(),
+                      amount_string: amount_string.into(),
+                      payee: None,
+                      category: None,
+                      recurring: None
+                    }
+    }
+
+    pub fn with_payee<S>(mut self, payee: S) -> Self
+        where S: Into<String> {
+        self.payee = Some(payee.into());
+        self
+    }
+
+    pub fn with_category<S>(mut self, category: S) -> Self
+        where S: Into<String> {
+        self.category = Some(category.into());
+        self
+    }
+
+    pub fn with_recurring<S>(mut self, name: S) -> Self
+        where S: Into<String> {
+        self.recurring = Some(name.into());
+        self
+    }
+
+    /// Reads `txn|date|amount`, optionally followed by `|payee=...`,
+    /// `|category=...` and `|recurring=...` fields.
+    pub fn from_line(line: &str) -> Option<Self> {
+        if !line.starts_with(PREFIX) {
+            return None
+        }
+        let fields = split_fields(&line[PREFIX.len()..]);
+        let mut transaction = Transaction::new(fields[0].as_str(), fields.get(1).map_or("", |a| a.as_str()));
+        for field in fields.iter().skip(2) {
+            match field.split_once('=') {
+                Some(("payee", payee)) => transaction.payee = Some(payee.to_string()),
+                Some(("category", category)) => transaction.category = Some(category.to_string()),
+                Some(("recurring", name)) => transaction.recurring = Some(name.to_string()),
+                _ => {}
+            }
+        }
+        Some(transaction)
+    }
+
+    /// Dates and amounts follow the same rules as balance entries.
+    pub fn validate(&self) -> Validation {
+        self.as_entry().validate()
+    }
+
+    fn as_entry(&self) -> Entry {
+        Entry::new(self.date_string.as_str(), self.amount_string.as_str())
+    }
+
+    pub fn amount(&self) -> f64 {
+        self.as_entry().amount()
+    }
+
+    pub fn category(&self) -> &str {
+        self.category.as_ref().map_or("uncategorized", |c| c.as_str())
+    }
+}
+
+impl fmt::Display for Transaction {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}{}|{}", PREFIX, self.date_string, self.amount_string)?;
+        if let Some(ref payee) = self.payee {
+            write!(f, "|payee={}", escape(payee))?;
+        }
+        if let Some(ref category) = self.category {
+            write!(f, "|category={}", escape(category))?;
+        }
+        if let Some(ref name) = self.recurring {
+            write!(f, "|recurring={}", escape(name))?;
+        }
+        writeln!(f)
+    }
+}
+
+pub fn is_transaction_line(line: &str) -> bool {
+    line.starts_with(PREFIX)
+}
+
+/// Every transaction in the ledger; none when there is no ledger yet.
+pub fn read_transactions(file_path: &Path) -> io::Result<Vec<Transaction>> {
+    match storage::open(file_path).transactions() {
+        Err(ref e) if e.kind() == ErrorKind::NotFound => Ok(Vec::new()),
+        transactions => transactions
+    }
+}
+
+/// Transactions dated after `start` and up to and including `end`.
+pub fn between<'a>(transactions: &'a [Transaction], start: &str, end: &str) -> Vec<&'a Transaction> {
+    transactions.iter()
+                .filter(|t| t.date_string.as_str() > start && t.date_string.as_str() <= end)
+                .collect()
+}
+
+pub fn run_txn(data_path: &Path, matches: &ArgMatches) -> Result<bool, Error> {
+    let submatches = matches.subcommand_matches("txn").ok_or(Error::InputError)?;
+    match submatches.subcommand() {
+        ("add", Some(add_matches)) => {
+            let transaction = build_transaction(add_matches, &date::today())?;
+            match transaction.validate() {
+                Validation::Valid => journal::appended(data_path, "txn add", &[Line::from(transaction)]),
+                invalid => {
+                    println!("{}", invalid_message(&transaction.as_entry(), &invalid));
+                    Err(Error::InputError)
+                }
+            }
+        },
+        _ => Err(Error::InputError)
+    }
+}
+
+fn build_transaction(submatches: &ArgMatches, today: &time::Tm) -> Result<Transaction, Error> {
+    let input = submatches.value_of("date").ok_or(Error::InputError)?;
+    let amount = submatches.value_of("amount").ok_or(Error::InputError)?;
+    let date = normalize_date(input, today).map_err(|e| {
+        println!("{}", invalid_date_message(input, &e));
+        Error::InputError
+    })?;
+    let mut transaction = Transaction::new(date, normalize_amount(amount).unwrap_or_else(|| amount.to_string()));
+    if let Some(payee) = submatches.value_of("payee") {
+        transaction = transaction.with_payee(payee);
+    }
+    if let Some(category) = submatches.value_of("category") {
+        transaction = transaction.with_category(category);
+    }
+    Ok(transaction)
+}
+
+#[cfg(test)]
+mod test {
+    use super::{ Transaction, between, is_transaction_line };
+
+    #[test]
+    fn transaction_round_trips_through_line() {
+        let transaction = Transaction::new("2016-03-02", "-45.20").with_payee("Joe's | Diner")
+                                                                   .with_category("dining");
+        let line = format!("{}", transaction);
+
+        assert_eq!(line, "txn|2016-03-02|-45.20|payee=Joe's \\| Diner|category=dining\n");
+        assert!(is_transaction_line(&line));
+        assert_eq!(Transaction::from_line(line.trim_end()), Some(transaction));
+        assert_eq!(Transaction::from_line("2016-03-02|100.00"), None);
+    }
+
+    #[test]
+    fn selects_transactions_within_interval() {
+        let transactions = vec![Transaction::new("2016-02-01", "-10.00"),
+                                Transaction::new("2016-02-15", "-20.00"),
+                                Transaction::new("2016-03-05", "-30.00"),
+                                Transaction::new("2016-03-06", "-40.00")
+                               ];
+
+        let amounts = between(&transactions, "2016-02-01", "2016-03-05").iter()
+                                                                         .map(|t| t.amount())
+                                                                         .collect::<Vec<f64>>();
+
+        assert_eq!(amounts, vec![-20.0, -30.0]);
+    }
+}
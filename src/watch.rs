@@ -0,0 +1,149 @@
+use std::collections::hash_map::DefaultHasher;
+use std::fs;
+use std::hash::{ Hash, Hasher };
+use std::path::{ Path, PathBuf };
+use std::thread;
+use std::time::{ Duration, SystemTime };
+
+/// Files up to this size are hashed as well, since rewriting one in place
+/// at the same length can leave its modification time unchanged on file
+/// systems with coarse timestamps.
+const HASHED: u64 = 64 * 1024;
+
+/// What `stat` says about a version of a file, with a hash of the contents
+/// of a small one. It is looked up by path each time, so a file replaced by
+/// a rename shows up as a change just as an append does. A same-length
+/// rewrite of a larger file within one tick of its timestamp goes unseen.
+#[derive(PartialEq, Eq, Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct Stamp {
+    modified: SystemTime,
+    len: u64,
+    inode: u64,
+    digest: Option<u64>
+}
+
+#[cfg(unix)]
+fn inode(metadata: &fs::Metadata) -> u64 {
+    use std::os::unix::fs::MetadataExt;
+    metadata.ino()
+}
+
+#[cfg(not(unix))]
+fn inode(_: &fs::Metadata) -> u64 {
+    0
+}
+
+/// `None` while the file is missing, as it may be mid-replace.
+pub fn stamp(path: &Path) -> Option<Stamp> {
+    let metadata = fs::metadata(path).ok()?;
+    let digest = if metadata.len() <= HASHED { Some(digest(path)?) } else { None };
+    Some(Stamp { modified: metadata.modified().ok()?, len: metadata.len(), inode: inode(&metadata), digest })
+}
+
+fn digest(path: &Path) -> Option<u64> {
+    let mut hasher = DefaultHasher::new();
+    fs::read(path).ok()?.hash(&mut hasher);
+    Some(hasher.finish())
+}
+
+/// Polls a file for changes.
+pub struct Watcher {
+    path: PathBuf,
+    last: Option<Stamp>,
+    poll: Duration,
+    settle: Duration,
+    sleep: Box<dyn FnMut(Duration)>
+}
+
+impl Watcher {
+    /// Checks every `poll`; a change counts once the file has stayed the
+    /// same for `settle`, so a burst of writes is seen as one.
+    pub fn new(path: &Path, poll: Duration, settle: Duration) -> Self {
+        Watcher { path: path.to_path_buf(), last: stamp(path), poll, settle, sleep: Box::new(thread::sleep) }
+    }
+
+    /// Blocks until the file has changed and settled.
+    pub fn wait(&mut self) {
+        loop {
+            (self.sleep)(self.poll);
+            let current = stamp(&self.path);
+            if current != self.last && current.is_some() {
+                break
+            }
+        }
+        let mut current = stamp(&self.path);
+        loop {
+            (self.sleep)(self.settle);
+            let after = stamp(&self.path);
+            if after == current && after.is_some() {
+                break
+            }
+            current = after;
+        }
+        self.last = current;
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::cell::RefCell;
+    use std::env;
+    use std::fs;
+    use std::path::{ Path, PathBuf };
+    use std::rc::Rc;
+    use std::thread;
+    use std::time::Duration;
+    use super::{ Watcher, stamp };
+
+    fn scratch(name: &str) -> PathBuf {
+        let path = env::temp_dir().join(format!("budget_watch_{}_{}", name, std::process::id()));
+        fs::write(&path, "2016-01-01|1000.00\n").unwrap();
+        path
+    }
+
+    fn watcher(path: &Path) -> Watcher {
+        Watcher::new(path, Duration::from_millis(5), Duration::from_millis(60))
+    }
+
+    #[test]
+    fn waits_for_a_burst_of_writes_to_settle() {
+        let path = scratch("burst");
+        let mut watcher = watcher(&path);
+        let (writer, naps) = (path.clone(), Rc::new(RefCell::new(Vec::new())));
+        let slept = naps.clone();
+        // the first five naps each see another same-length write land
+        watcher.sleep = Box::new(move |duration| {
+            slept.borrow_mut().push(duration);
+            let n = slept.borrow().len();
+            if n <= 5 {
+                fs::write(&writer, format!("2016-01-01|1000.00\n2016-02-0{}|1200.00\n", n)).unwrap();
+            }
+        });
+
+        watcher.wait();
+
+        let (poll, settle) = (Duration::from_millis(5), Duration::from_millis(60));
+        assert_eq!(*naps.borrow(), vec![poll, settle, settle, settle, settle, settle]);
+        assert_eq!(watcher.last, stamp(&path));
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn sees_a_file_replaced_by_rename() {
+        let path = scratch("replace");
+        let replacement = path.with_extension("tmp");
+        let mut watcher = watcher(&path);
+        let (from, to) = (replacement.clone(), path.clone());
+        thread::spawn(move || {
+            thread::sleep(Duration::from_millis(20));
+            fs::write(&from, "2016-01-01|1000.00\n").unwrap();
+            fs::rename(&from, &to).unwrap();
+        });
+
+        watcher.wait();
+
+        assert_eq!(fs::read_to_string(&path).unwrap(), "2016-01-01|1000.00\n");
+        assert_eq!(watcher.last, stamp(&path));
+        fs::remove_file(path).unwrap();
+    }
+}